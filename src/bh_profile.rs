@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+//only the layout rico3 was written for is embedded, headers of other releases and platforms need a profile file
+const EMBEDDED_PROFILES: [&str; 1] = [include_str!("bh_profile_default.json")];
+const PROFILE_VERSION: u32 = 1;
+
+/// Layout of a buffer header (X$BH) of an Oracle release and platform: its size and the offsets of its fields.
+/// Profiles are JSON files with profile_version 1. rdba, objd and ba are required, the rest is optional.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BhProfile {
+    pub profile_version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub size: usize,
+    /// Alignment of headers in memory, used when scanning for them
+    #[serde(default = "default_align")]
+    pub align: usize,
+    pub rdba: usize,
+    pub objd: usize,
+    pub ba: usize,
+    pub flag1: Option<usize>,
+    pub flag2: Option<usize>,
+    /// X$BH.FLAG (4 bytes), whose low bit marks dirty buffers
+    pub dirty_flag: Option<usize>,
+    pub tch: Option<usize>,
+    pub nxt_hash: Option<usize>,
+    pub prv_hash: Option<usize>,
+    pub nxt_repl: Option<usize>,
+    pub prv_repl: Option<usize>,
+    /// X$BH.STATE (4 bytes), values as V$BH.STATUS decodes them: 0 free, 1 xcur, 2 scur, 3 cr, 4 read, 5 mrec, 6 irec, 7 write, 8 pi
+    pub state: Option<usize>,
+    #[serde(default = "default_current_states")]
+    pub current_states: Vec<u32>,
+    #[serde(default = "default_cr_states")]
+    pub cr_states: Vec<u32>,
+    /// Without a state offset, tag the copy of a block with the newest block SCN current and older copies CR.
+    /// CR clones are made from the current buffer by rolling changes back, so they are never newer than it.
+    #[serde(default)]
+    pub derive_state: bool,
+    /// Bits of dirty_flag set for dirty buffers, V$BH.DIRTY is bitand(X$BH.FLAG, 1)
+    #[serde(default = "default_dirty_mask")]
+    pub dirty_mask: u32,
+}
+
+fn default_align() -> usize {
+    8
+}
+
+//xcur, scur - V$BH.STATUS
+fn default_current_states() -> Vec<u32> {
+    vec![1, 2]
+}
+
+//cr
+fn default_cr_states() -> Vec<u32> {
+    vec![3]
+}
+
+fn default_dirty_mask() -> u32 {
+    1
+}
+
+/// Fields of a buffer header read with a profile, fields missing in the profile are None
+#[derive(Debug, Clone)]
+pub struct Xbh {
+    pub rdba: u32,
+    pub objd: u32,
+    pub ba: u64,
+    pub flag1: Option<u32>,
+    pub flag2: Option<u32>,
+    pub dirty_flag: Option<u32>,
+    pub tch: Option<u8>,
+    pub state: Option<u32>,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+impl BhProfile {
+    pub fn from_json(json: &str) -> Result<BhProfile, String> {
+        let profile: BhProfile = serde_json::from_str(json).map_err(|e| format!("Wrong buffer header profile: {}", e))?;
+        if profile.profile_version != PROFILE_VERSION {
+            return Err(format!("Buffer header profile {} has version {}, only version {} is supported", profile.name, profile.profile_version, PROFILE_VERSION));
+        }
+        if profile.align == 0 {
+            return Err(format!("Buffer header profile {} has align 0, use 1 for headers at any address", profile.name));
+        }
+        //(offset, length) of the fields
+        let fields = [Some((profile.rdba, 4)), Some((profile.objd, 4)), Some((profile.ba, 8)), profile.flag1.map(|o| (o, 4)), profile.flag2.map(|o| (o, 4)),
+            profile.dirty_flag.map(|o| (o, 4)), profile.tch.map(|o| (o, 1)), profile.nxt_hash.map(|o| (o, 8)), profile.prv_hash.map(|o| (o, 8)),
+            profile.nxt_repl.map(|o| (o, 8)), profile.prv_repl.map(|o| (o, 8)), profile.state.map(|o| (o, 4))];
+        if fields.iter().flatten().any(|(offset, len)| offset.checked_add(*len).map(|end| end > profile.size).unwrap_or(true)) {
+            return Err(format!("Buffer header profile {} has fields outside of its size {}", profile.name, profile.size));
+        }
+        Ok(profile)
+    }
+
+    pub fn from_file(fname: &str) -> Result<BhProfile, String> {
+        let json = fs::read_to_string(fname).map_err(|e| format!("Can't read buffer header profile {}: {}", fname, e))?;
+        BhProfile::from_json(&json)
+    }
+
+    /// data holds at least size bytes of a header
+    pub fn parse(&self, data: &[u8]) -> Xbh {
+        Xbh {
+            rdba: read_u32(data, self.rdba),
+            objd: read_u32(data, self.objd),
+            ba: u64::from_ne_bytes(data[self.ba..self.ba + 8].try_into().unwrap()),
+            flag1: self.flag1.map(|o| read_u32(data, o)),
+            flag2: self.flag2.map(|o| read_u32(data, o)),
+            dirty_flag: self.dirty_flag.map(|o| read_u32(data, o)),
+            tch: self.tch.map(|o| data[o]),
+            state: self.state.map(|o| read_u32(data, o)),
+        }
+    }
+
+    /// current, CR, or unknown when the profile has no state or the state is neither.
+    /// newest tells whether the buffer holds the newest copy of its block, used with derive_state.
+    pub fn buffer_state(&self, xbh: &Xbh, newest: bool) -> String {
+        match xbh.state {
+            Some(state) if self.current_states.contains(&state) => "current".to_string(),
+            Some(state) if self.cr_states.contains(&state) => "CR".to_string(),
+            Some(state) => format!("state {}", state),
+            None if self.derive_state && newest => "current".to_string(),
+            None if self.derive_state => "CR".to_string(),
+            None => "unknown".to_string(),
+        }
+    }
+
+    /// dirty, clean, or unknown when the profile has no dirty_flag
+    pub fn buffer_dirty(&self, xbh: &Xbh) -> String {
+        match xbh.dirty_flag {
+            Some(flag) if flag & self.dirty_mask != 0 => "dirty".to_string(),
+            Some(_) => "clean".to_string(),
+            None => "unknown".to_string(),
+        }
+    }
+}
+
+pub fn embedded_profiles() -> Vec<BhProfile> {
+    EMBEDDED_PROFILES.iter().map(|json| BhProfile::from_json(json).unwrap()).collect()
+}
+
+/// Profiles to use: auto (the embedded default profile, the only one shipped), the name of an embedded profile,
+/// a profile file, or a directory of profile files (detected among them and the embedded one)
+pub fn load_profiles(spec: Option<&str>) -> Result<Vec<BhProfile>, String> {
+    let embedded = embedded_profiles();
+    let spec = match spec {
+        None | Some("auto") => return Ok(embedded),
+        Some(spec) => spec,
+    };
+    if let Some(profile) = embedded.iter().find(|p| p.name == spec) {
+        return Ok(vec![profile.clone()]);
+    }
+
+    let path = Path::new(spec);
+    if path.is_dir() {
+        let mut fnames: Vec<String> = fs::read_dir(path).map_err(|e| e.to_string())?.flatten()
+            .map(|e| e.path().to_string_lossy().to_string()).filter(|f| f.ends_with(".json")).collect();
+        fnames.sort();
+        let mut profiles = embedded;
+        for fname in fnames {
+            profiles.push(BhProfile::from_file(&fname)?);
+        }
+        Ok(profiles)
+    } else {
+        Ok(vec![BhProfile::from_file(spec)?])
+    }
+}
@@ -16,13 +16,22 @@ use std::thread;
 use crossbeam_channel::bounded;
 use crossbeam_channel::Receiver;
 use crate::oracle_decoder::OracleType;
+use crate::oracle_decoder::DecodeOptions;
 use crate::oracle_decoder;
-use proc_maps::{get_process_maps, Pid};
+use crate::raw_output;
+use crate::raw_output::RawOutput;
+use crate::lob_organizer;
+use crate::lob_locator;
+use crate::row_organizer;
+use crate::row_organizer::RowColumns;
+use crate::object_decoder;
+use crate::memory_source;
+use crate::memory_source::{MemoryRegion, MemorySource};
 use std::time::SystemTime;
 use chrono::DateTime;
 use chrono::Utc;
 
-const BLOCK_SIZE: u64 = 8192;
+pub const BLOCK_SIZE: u64 = 8192;
 
 
 #[derive(BinRead)]
@@ -154,6 +163,13 @@ struct ChunkData {
     chunk_bytes: Vec<u8>,
     chunk_offset: u64,
 }
+
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub decode: DecodeOptions,
+    pub raw_output: RawOutput,
+    pub long_inline_size: usize,
+}
  
 fn write_bytes_to_file(fname: String, bytes_val:Vec<u8>) {
     let mut f_obj = File::options().append(true).create(true).open(fname).unwrap();
@@ -167,7 +183,7 @@ fn write_text_to_file(fname: String, line: String) {
     f_obj.write_all(line_new.as_bytes());
 }
 
-fn write_log(workdir: String, action: String) {
+pub fn write_log(workdir: String, action: String) {
     let now = SystemTime::now();
     let dt: DateTime<Utc> = now.into();
     let fname = format!("{}/rico3.log",workdir);
@@ -179,6 +195,16 @@ fn write_log(workdir: String, action: String) {
     f_obj.flush();
 }
 
+//LOB segment blocks go to lobs.dat (they carry no objd), LOB index blocks to <objd>.idx.dat
+fn consolidate_lob_block(block_data: &[u8], workdir: &str) {
+    if lob_organizer::is_lob_block(block_data) {
+        write_bytes_to_file(format!("{}/lobs.dat", workdir), block_data.to_vec());
+    } else if lob_organizer::is_lob_index_block(block_data) {
+        let objd = u32::from_ne_bytes(block_data[24..28].try_into().unwrap());
+        write_bytes_to_file(format!("{}/{}.idx.dat", workdir, objd), block_data.to_vec());
+    }
+}
+
 fn consolidate_chunk(chunk_bytes: Vec<u8>, workdir: String) {
     let chunk_len = chunk_bytes.len();
     let chunk_len_blocks = chunk_len as u64 / BLOCK_SIZE;
@@ -190,6 +216,8 @@ fn consolidate_chunk(chunk_bytes: Vec<u8>, workdir: String) {
             let objd = u32::from_ne_bytes(block_data[24..28].try_into().unwrap());
             let f_obj_name = format!("{}/{}.dat", workdir, objd);
             write_bytes_to_file(f_obj_name, block_data.to_vec());
+        } else {
+            consolidate_lob_block(block_data, &workdir);
         }
         position += 1;
     }
@@ -209,6 +237,8 @@ fn consolidate_chunk_parallel(rc: Receiver<Vec<u8>>, workdir: String, worker_id:
                 let objd = u32::from_ne_bytes(block_data[24..28].try_into().unwrap());
                 let f_obj_name = format!("{}/{}.dat", workdir, objd);
                 write_bytes_to_file(f_obj_name, block_data.to_vec());
+            } else {
+                consolidate_lob_block(block_data, &workdir);
             }
             position += 1;
         }
@@ -234,6 +264,8 @@ fn consolidate_chunk_parallel2(rc: Receiver<ChunkData>, workdir: String, worker_
                 let rba = u32::from_ne_bytes(block_data[4..8].try_into().unwrap());
                 let block_meta = format!("Block: {} found at offset: {}", rba, chunk_data.chunk_offset+position*BLOCK_SIZE);
                 write_text_to_file(f_obj_log_name, block_meta);
+            } else {
+                consolidate_lob_block(block_data, &workdir);
             }
             position += 1;
         }
@@ -241,23 +273,8 @@ fn consolidate_chunk_parallel2(rc: Receiver<ChunkData>, workdir: String, worker_
     println!("Stopping worker {}", worker_id);
 }
 
-pub fn consolidate_objects_from_memory(pid: u32, memory_size: u64, workdir: String, parallel: u8) {
-    println!("Processing pid {} for memory size {}", pid, memory_size);
-    let maps = get_process_maps(pid as Pid).unwrap();
-    let mut scan_from: u64 = 0;
-    let mut scan_to: u64 = 0;
-    for map in maps {
-        if (map.size() as u64) == memory_size {
-            scan_from = map.start() as u64;
-            scan_to   = scan_from + (map.size() as u64);
-            println!("Found map at the start offset = {} \t end offset = {}\n", scan_from, scan_to);
-            break;
-        }
-    }
-
-    let mut buffer = [0; 1_048_576];
-    let fname = format!("/proc/{}/mem", pid);
-    let mut f = File::open(&fname).unwrap(); 
+pub fn consolidate_objects_from_memory(source: &mut dyn MemorySource, regions: Vec<MemoryRegion>, workdir: String, parallel: u8, readers: u8, skip_granularity: u64) {
+    println!("Processing {} memory regions", regions.len());
 
     let (tx, rx) = bounded::<ChunkData>(parallel as usize);
     let mut threads: Vec<thread::JoinHandle<_>> = Vec::new();
@@ -267,25 +284,22 @@ pub fn consolidate_objects_from_memory(pid: u32, memory_size: u64, workdir: Stri
         threads.push(thread::spawn(move || {consolidate_chunk_parallel2(rx, w, p)}));
     }
 
-    f.seek(SeekFrom::Start(scan_from));
-    loop {
-        let chunk_pos = f.seek(SeekFrom::Current(0)).unwrap();
-        let res = f.read(&mut buffer);
-        if res.is_err() {
-            break;
-        }
-        let out_bytes = res.unwrap();
-        if out_bytes == 0 {
-            break;
-        }
+    for region in &regions {
+        let report = format!("Scanning {} from {:#x} to {:#x}", region.name, region.start, region.start + region.size);
+        println!("{}", report);
+        write_log(workdir.clone(), report);
+    }
 
-        let pos = f.seek(SeekFrom::Current(0)).unwrap();
-        if pos >= scan_to {
-            break;
-        }
-        let chunk = ChunkData{chunk_bytes: buffer.to_vec(), chunk_offset: chunk_pos};
-        tx.send(chunk);
+    let stats = memory_source::read_regions_parallel(source, &regions, readers, skip_granularity, |chunk_offset, chunk_bytes| {
+        tx.send(ChunkData{chunk_bytes, chunk_offset}).unwrap();
+    });
+    for (skip_from, skip_to) in &stats.skipped {
+        write_log(workdir.clone(), format!("Skipped unreadable memory from {:#x} to {:#x}", skip_from, skip_to));
     }
+    let report = format!("Read {} bytes, {} unreadable ranges skipped", stats.bytes_read, stats.skipped.len());
+    println!("{}", report);
+    write_log(workdir.clone(), report);
+
     drop(tx);
     for t in threads {
         t.join().unwrap();
@@ -324,7 +338,7 @@ pub fn consolidate_objects_from_file(fname: String, workdir: String, parallel: u
     }
 }
 
-pub fn extract_from_file(fname: String, workdir: String, parallel: u8) {
+pub fn extract_from_file(fname: String, workdir: String, parallel: u8, options: ExtractOptions) {
     println!("Processing file {} ", fname);
     let mut buffer = [0; 1_048_576];
     let file_path = format!("{}/{}", workdir, fname);
@@ -335,7 +349,8 @@ pub fn extract_from_file(fname: String, workdir: String, parallel: u8) {
     for p in 0..parallel  {
         let rx = rx.clone();
         let w = workdir.clone();
-        threads.push(thread::spawn(move || {extract_chunk_parallel(rx, w, p)}));
+        let o = options.clone();
+        threads.push(thread::spawn(move || {extract_chunk_parallel(rx, w, p, o)}));
     }
 
     loop {
@@ -354,9 +369,19 @@ pub fn extract_from_file(fname: String, workdir: String, parallel: u8) {
     for t in threads {
         t.join().unwrap();
     }
+    row_organizer::assemble_rows(&workdir, &options, false);
+    lob_organizer::reassemble_lobs(&workdir, &options.decode);
+    object_decoder::link_nested_tables(&workdir);
+}
+
+/// After the last data file: rows whose head piece never came, with the LOBs and nested tables they refer to
+pub fn finish_extraction(workdir: &str, options: &ExtractOptions) {
+    row_organizer::assemble_rows(workdir, options, true);
+    lob_organizer::reassemble_lobs(workdir, &options.decode);
+    object_decoder::link_nested_tables(workdir);
 }
 
-fn extract_chunk_parallel(rc: Receiver<Vec<u8>>, workdir: String, worker_id: u8) {
+fn extract_chunk_parallel(rc: Receiver<Vec<u8>>, workdir: String, worker_id: u8, options: ExtractOptions) {
     println!("Starting worker {}", worker_id);
     for chunk_bytes in rc {
         
@@ -368,7 +393,7 @@ fn extract_chunk_parallel(rc: Receiver<Vec<u8>>, workdir: String, worker_id: u8)
             let block_data = &chunk_bytes[(position*BLOCK_SIZE) as usize..(position+1) as usize * BLOCK_SIZE as usize];
             if block_data[0] == 6 && block_data[20] == 1 {
                 write_log(workdir.clone(), format!("Trying to extract from block {}", position));
-                extract_block(block_data.to_vec(), workdir.clone());
+                extract_block(block_data.to_vec(), workdir.clone(), &options);
             }
             position += 1;
         }
@@ -376,12 +401,101 @@ fn extract_chunk_parallel(rc: Receiver<Vec<u8>>, workdir: String, worker_id: u8)
     println!("Stopping worker {}", worker_id);
 }
 
-fn extract_block(block_data: Vec<u8>, workdir: String) {
+//NULL columns (length 255) are None
+fn read_columns(block_cursor: &mut Cursor<Vec<u8>>, no_columns: u8) -> RowColumns {
+    let mut row_columns: RowColumns = Vec::new();
+    for _ in 0..no_columns {
+        let mut raw_bytes: Option<Vec<u8>> = None;
+        let col_len: u8 = block_cursor.read_ne().unwrap();
+
+        if col_len == 254 {
+            let column_data: Result<ColumnDataLong, binread::Error> = block_cursor.read_ne();
+            if let Ok(column_data) = column_data {
+                raw_bytes = Some(column_data.col_data);
+            }
+        } else if col_len < 254 {
+            block_cursor.seek(SeekFrom::Current(-1)).unwrap();
+            let column_data: Result<ColumnData, binread::Error> = block_cursor.read_ne();
+            if let Ok(column_data) = column_data {
+                raw_bytes = Some(column_data.col_data);
+            }
+        }
+        row_columns.push(raw_bytes);
+    }
+    row_columns
+}
+
+/// Decodes a row and appends it to <objd>.csv (and <objd>.raw).
+/// Columns listed in long_columns were joined from several row pieces and are treated as LONG when the dictionary doesn't say otherwise.
+pub fn write_row(row_columns: RowColumns, long_columns: &[usize], objd: u32, rdba: u32, slot: u16, workdir: &str, options: &ExtractOptions) {
+    let columns = options.decode.dictionary.table(objd).map(|t| t.columns.clone()).unwrap_or_default();
+    let mut row_string: String = String::new();
+    let mut raw_string: String = String::new();
+
+    if let Some(Some(set_id)) = options.decode.dictionary.nested_table_id_column(objd).and_then(|k| row_columns.get(k)) {
+        object_decoder::queue_nested_table_row(workdir, objd, set_id, rdba, slot);
+    }
+
+    //attributes of object columns are part of the object's value, only their raw bytes are written on their own
+    let mut object_end = 0;
+    for (i, raw_bytes) in row_columns.iter().enumerate() {
+        let mut column_data_value: OracleType =  OracleType { data_type: "NULL".to_string(), value: "NULL".to_string() };
+        let mut token = String::new();
+        if let Some(raw_bytes) = raw_bytes {
+            if options.raw_output != RawOutput::None {
+                token = raw_output::raw_token(raw_bytes, options.raw_output, workdir, objd);
+            }
+        }
+        raw_string = format!("{}|{}", raw_string, token);
+        if i < object_end {
+            continue;
+        }
+
+        let object_type = columns.get(i).and_then(|c| options.decode.dictionary.type_def(&c.data_type)).filter(|t| t.is_object());
+        if let Some(object_type) = object_type {
+            let (value, width) = object_decoder::decode_object(&row_columns[i..], object_type, &options.decode);
+            column_data_value.value = value;
+            object_end = i + width;
+        } else if let Some(raw_bytes) = raw_bytes.clone() {
+            let column_type = columns.get(i).map(|c| oracle_decoder::base_type(&c.data_type));
+            if let Some(long_raw) = row_organizer::long_kind(column_type.as_deref(), long_columns.contains(&i), &raw_bytes, &options.decode) {
+                column_data_value.value = row_organizer::long_value(&raw_bytes, long_raw, (objd, rdba, slot, i), workdir, options);
+            } else {
+                let locator = lob_locator::parse_locator(&raw_bytes).ok();
+                column_data_value = oracle_decoder::decode_column(raw_bytes.clone(), &columns, i, &options.decode);
+                if let Some(column) = columns.get(i) {
+                    if let Some(storage_objd) = options.decode.dictionary.storage_table(column) {
+                        object_decoder::queue_nested_table(workdir, (objd, rdba, slot), &column.name, storage_objd, &raw_bytes);
+                    }
+                }
+                if let Some(locator) = locator.filter(|_| column_data_value.value.starts_with("LOBID:")) {
+                    lob_organizer::queue_lob(workdir, &locator, &raw_bytes);
+                    column_data_value.value = lob_organizer::lob_file_name(&locator);
+                }
+                if column_data_value.data_type == "Unrecognized" && options.raw_output != RawOutput::None {
+                    column_data_value.value = token.clone();
+                }
+            }
+        }
+
+        row_string = format!("{}|{}", row_string, column_data_value.value);
+    }
+
+    if row_string.len() > 1 {
+        write_text_to_file(format!("{}/{}.csv", workdir, objd), row_string);
+        if options.raw_output != RawOutput::None {
+            let raw_line = format!("{}|{}|{}{}", objd, rdba, slot, raw_string);
+            write_text_to_file(format!("{}/{}.raw", workdir, objd), raw_line);
+        }
+    }
+}
+
+fn extract_block(block_data: Vec<u8>, workdir: String, options: &ExtractOptions) {
     let mut block_cursor = Cursor::new(block_data);
     let kcbh: Kcbh = block_cursor.read_ne().unwrap();
     let ktbbh: Ktbbh = block_cursor.read_ne().unwrap();
 
-    let end_of_ktbbh = block_cursor.seek(SeekFrom::Current(0)).unwrap() as i64;
+    let end_of_ktbbh = block_cursor.stream_position().unwrap() as i64;
 
     let mod_flags: ModFlags = block_cursor.read_ne().unwrap();
     let mut offset_mod: i64 = 0;
@@ -405,8 +519,6 @@ fn extract_block(block_data: Vec<u8>, workdir: String) {
 
     let delcared_rows_offset = end_of_ktbbh + offset_mod + 2;
 
-    let mut row_string: String = String::new();
-
     let mut row_pointer_mod: i64 = 0;
     if mod_flags.flag1 == 0 && mod_flags.flag2 == 0 {
         row_pointer_mod = 100;
@@ -432,7 +544,7 @@ fn extract_block(block_data: Vec<u8>, workdir: String) {
             let mut row_pointer: i64 = row_pointer as i64;
             row_pointer += row_pointer_mod + 24 * ((ktbbh.ktbbhict as i64) - 2);
 
-            if row_pointer as i64 > (2 * kdbh.kdbhnrow as i64 + end_of_ktbbh + offset_mod + 14 + 4 * num_of_tables as i64) && row_pointer as u64 <= BLOCK_SIZE - 8 {
+            if row_pointer > (2 * kdbh.kdbhnrow as i64 + end_of_ktbbh + offset_mod + 14 + 4 * num_of_tables as i64) && row_pointer as u64 <= BLOCK_SIZE - 8 {
 
                 block_cursor.seek(SeekFrom::Start(row_pointer as u64)).unwrap();
                 let row_header: u8 = block_cursor.read_ne().unwrap();
@@ -442,40 +554,118 @@ fn extract_block(block_data: Vec<u8>, workdir: String) {
                     block_cursor.seek(SeekFrom::Start(row_pointer as u64 + 2)).unwrap();
                     let no_columns: u8 = block_cursor.read_ne().unwrap();
                     block_cursor.seek(SeekFrom::Start(row_pointer as u64 + 3)).unwrap();
-                    for i in 0..no_columns {
-                        let mut column_data_value: OracleType =  OracleType { data_type: "NULL".to_string(), value: "NULL".to_string() };
-                        let col_len: u8 = block_cursor.read_ne().unwrap();
-
-                        if col_len == 254 {
-                            let column_data: Result<ColumnDataLong, binread::Error> = block_cursor.read_ne();
-                            if column_data.is_ok() {
-                                let column_data = column_data.unwrap();
-                                column_data_value = oracle_decoder::guess_type(column_data.col_data);
-                            }
-                        } else if col_len < 254 {
-                            block_cursor.seek(SeekFrom::Current(-1));
-                            let column_data: Result<ColumnData, binread::Error> = block_cursor.read_ne();
-                            if column_data.is_ok() {
-                                let column_data = column_data.unwrap();
-                                column_data_value = oracle_decoder::guess_type(column_data.col_data);
-                            }
-                        }
-
-                        row_string = format!("{}|{}", row_string, column_data_value.value);
-                        
-                    }
-                } else if row_header == 60 {
+                    let row_columns = read_columns(&mut block_cursor, no_columns);
+                    write_row(row_columns, &[], ktbbh.ktbbhsid, kcbh.rdba_kcbh, i as u16, &workdir, options);
+                } else if row_header & row_organizer::ROW_FLAG_DELETED != 0 {
                     deleted_rows += 1;
+                } else if row_organizer::is_row_piece(row_header) {
+                    block_cursor.seek(SeekFrom::Start(row_pointer as u64 + 2)).unwrap();
+                    let no_columns: u8 = block_cursor.read_ne().unwrap();
+                    //the first piece of a migrated row (F without H) carries the rowid of its head, hrid
+                    if row_header & (row_organizer::ROW_FLAG_HEAD | row_organizer::ROW_FLAG_FIRST) == row_organizer::ROW_FLAG_FIRST {
+                        block_cursor.seek(SeekFrom::Current(6)).unwrap();
+                    }
+                    let mut nrid: Option<(u32, u16)> = None;
+                    if row_header & row_organizer::ROW_FLAG_LAST == 0 {
+                        let nrid_dba: u32 = block_cursor.read_ne().unwrap();
+                        let nrid_slot: u16 = block_cursor.read_ne().unwrap();
+                        nrid = Some((nrid_dba, nrid_slot));
+                    }
+                    let row_columns = read_columns(&mut block_cursor, no_columns);
+                    let piece = row_organizer::RowPiece { rdba: kcbh.rdba_kcbh, slot: i as u16, flag: row_header, nrid, columns: row_columns };
+                    row_organizer::save_piece(&workdir, ktbbh.ktbbhsid, &piece);
                 }
 
             }
 
             row_pointer_offset += 2;
-            if row_string.len() > 1 {
-                write_text_to_file(format!("{}/{}.csv", workdir.clone(), ktbbh.ktbbhsid), row_string.clone());
-            }
-            row_string.clear();
         }
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OBJD: u32 = 74_512;
+    const RDBA: u32 = 0x0100_0085;
+
+    //8K table block with 2 ITLs (data header at 100) holding the rows, from the end of the block
+    fn data_block(rdba: u32, rows: &[Vec<u8>]) -> Vec<u8> {
+        let mut block_data = vec![0_u8; BLOCK_SIZE as usize];
+        block_data[0] = 6;
+        block_data[1] = 0xa2;
+        block_data[4..8].copy_from_slice(&rdba.to_ne_bytes());
+        block_data[8..12].copy_from_slice(&0x0023_4f1a_u32.to_ne_bytes());
+        block_data[14] = 1;
+        block_data[15] = 6;
+        block_data[20] = 1;
+        block_data[24..28].copy_from_slice(&OBJD.to_ne_bytes());
+        block_data[36] = 2;
+
+        //kdbh: ntab 1, nrow, then the table directory and the row directory
+        block_data[101] = 1;
+        block_data[102..104].copy_from_slice(&(rows.len() as i16).to_ne_bytes());
+        block_data[116..118].copy_from_slice(&(rows.len() as i16).to_ne_bytes());
+
+        let mut row_offset = 8000;
+        for (i, row) in rows.iter().enumerate() {
+            block_data[row_offset..row_offset + row.len()].copy_from_slice(row);
+            block_data[118 + 2 * i..120 + 2 * i].copy_from_slice(&((row_offset - 100) as i16).to_ne_bytes());
+            row_offset += row.len();
+        }
+
+        block_data.copy_within(8..10, 8188);
+        block_data[8190] = 6;
+        block_data[8191] = 1;
+        block_data
+    }
+
+    //head of a migrated row (--H-----) pointing to nrid
+    fn head_piece(nrid: (u32, u16)) -> Vec<u8> {
+        [&[0x20, 0, 0][..], &nrid.0.to_ne_bytes(), &nrid.1.to_ne_bytes()].concat()
+    }
+
+    //migrated row (----FL--) with its hrid, cc 2: NUMBER 1 and 'abc'
+    fn migrated_piece(hrid: (u32, u16)) -> Vec<u8> {
+        [&[0x0c, 0, 2][..], &hrid.0.to_ne_bytes(), &hrid.1.to_ne_bytes(), &[2, 0xc1, 2, 3], b"abc"].concat()
+    }
+
+    fn test_workdir(name: &str) -> (String, ExtractOptions) {
+        let workdir = std::env::temp_dir().join(format!("rico3_{}_{}", name, std::process::id())).to_string_lossy().to_string();
+        fs::create_dir_all(&workdir).unwrap();
+        (workdir, ExtractOptions { decode: DecodeOptions::default(), raw_output: RawOutput::None, long_inline_size: 4000 })
+    }
+
+    #[test]
+    fn migrated_row() {
+        let (workdir, options) = test_workdir("migrated_row");
+        extract_block(data_block(RDBA, &[head_piece((RDBA, 1)), migrated_piece((RDBA, 0))]), workdir.clone(), &options);
+        row_organizer::assemble_rows(&workdir, &options, false);
+
+        let csv = fs::read_to_string(format!("{}/{}.csv", workdir, OBJD)).unwrap();
+        fs::remove_dir_all(&workdir).unwrap();
+        assert_eq!(csv, "|1|abc\n");
+    }
+
+    //the migrated row is read from the first data file, its head from the second one
+    #[test]
+    fn migrated_row_head_in_later_file() {
+        let (workdir, options) = test_workdir("migrated_row_head_in_later_file");
+        let head_rdba = RDBA + 1;
+        extract_block(data_block(RDBA, &[migrated_piece((head_rdba, 0))]), workdir.clone(), &options);
+        row_organizer::assemble_rows(&workdir, &options, false);
+        let csv_file = format!("{}/{}.csv", workdir, OBJD);
+        assert!(fs::read_to_string(&csv_file).unwrap_or_default().is_empty());
+
+        extract_block(data_block(head_rdba, &[head_piece((RDBA, 0))]), workdir.clone(), &options);
+        row_organizer::assemble_rows(&workdir, &options, false);
+        finish_extraction(&workdir, &options);
+
+        let csv = fs::read_to_string(&csv_file).unwrap();
+        let pieces_left = fs::metadata(format!("{}/{}.pieces", workdir, OBJD)).is_ok();
+        fs::remove_dir_all(&workdir).unwrap();
+        assert_eq!(csv, "|1|abc\n");
+        assert!(!pieces_left);
+    }
+}
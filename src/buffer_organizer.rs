@@ -1,47 +1,25 @@
 use std::default;
 use std::fs::File;
-use std::io::Cursor;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
-use binread::BinRead;
-use binread::BinReaderExt;
 use clap::Parser;
 use std::{thread, time};
 use std::fs::read_to_string;
 use std::mem;
+use std::io::Write;
+use std::collections::HashMap;
+use crate::block_organizer;
+use crate::block_organizer::BLOCK_SIZE;
+use crate::bh_profile::{BhProfile, Xbh};
+use crate::memory_source;
+use crate::memory_source::{MemoryRegion, MemorySource};
+use crate::watch;
 
-
-#[derive(BinRead)]
-#[derive(Debug)]
-#[allow(dead_code)]
-struct Xbh {
-    ckpt1: u64, //@0
-    ckpt2: u64, //@8
-    #[br(count = 160)]
-    data1: Vec<u8>, //unknown data @16
-    nxt_hash: u64, //@176
-    prv_hash: u64, //@184
-    #[br(count = 8)]
-    data2: Vec<u8>, //unknown data @192
-    rdba: u32,  //@200
-    flag1: u32, //@204
-    objd: u32,  //@208
-    flag2: u32, //@212
-    dirty_flag: u32, //@216
-    #[br(count = 12)]
-    data3: Vec<u8>, //unknown data @220
-    nxt_repl: u64,   //@232
-    prv_repl: u64,   //@240
-    #[br(count = 3)]
-    data4: Vec<u8>, //unknown data @248
-    tch: u8, //@251
-    #[br(count = 4)]
-    data5: Vec<u8>, //unknown data @252
-    ba: u64, //@256
-    #[br(count = 96)]
-    data6: Vec<u8>, //unknown data @264
-}
+//header candidates are checked against their blocks every SCAN_RANGE bytes of a region
+const SCAN_RANGE: u64 = 64 * 1_048_576;
+//addresses of a list checked when choosing a profile for it
+const DETECT_SAMPLE: usize = 1000;
 
 fn read_bh_addr(fname: String) -> Vec<u64> {
     let mut addrs: Vec<u64> = Vec::new();
@@ -53,32 +31,45 @@ fn read_bh_addr(fname: String) -> Vec<u64> {
     addrs
 }
 
-pub fn visualize_buffers(fname: String, objd: u32, pid: u32) {
+//headers of the list read with the profile, None for unreadable addresses
+fn read_headers(source: &mut dyn MemorySource, addrs: &[u64], profile: &BhProfile) -> Vec<Option<Xbh>> {
+    let mut xbh_data = vec![0_u8; profile.size];
+    addrs.iter().map(|addr| match source.read_at(*addr, &mut xbh_data) {
+        Ok(n) if n == profile.size => Some(profile.parse(&xbh_data)),
+        _ => None,
+    }).collect()
+}
+
+/// The profile under which most of the listed headers point to their blocks
+pub fn detect_profile(source: &mut dyn MemorySource, addrs: &[u64], profiles: &[BhProfile]) -> BhProfile {
+    if profiles.len() == 1 {
+        return profiles[0].clone();
+    }
+    let sample = &addrs[..addrs.len().min(DETECT_SAMPLE)];
+    let mut best: Option<(usize, &BhProfile)> = None;
+    for profile in profiles {
+        let matched = read_headers(source, sample, profile).into_iter().flatten().filter(|xbh| points_to_block(source, xbh)).count();
+        println!("Buffer header profile {}: {} of {} headers point to their blocks", profile.name, matched, sample.len());
+        if best.map(|b| matched > b.0).unwrap_or(true) {
+            best = Some((matched, profile));
+        }
+    }
+    let profile = best.unwrap().1.clone();
+    println!("Using buffer header profile {}", profile.name);
+    profile
+}
+
+pub fn visualize_buffers(fname: String, objd: u32, source: &mut dyn MemorySource, profiles: &[BhProfile]) {
     let mut dots = 0;
-    let memfile = format!("/proc/{}/mem", pid);
-    let mut f = File::open(memfile).unwrap();
     let mut addrs = read_bh_addr(fname);
     addrs.sort();
-    let xbh_len = addrs.len();
-
-    f.seek(SeekFrom::Start(addrs[0])).unwrap();
-    
-    let buffer_len = addrs[xbh_len-1] - addrs[0] + 360;
-    let mut buffer = vec![0_u8; buffer_len as usize];
-    f.read(&mut buffer); 
+    let profile = detect_profile(source, &addrs, profiles);
 
-    let mut xbh_cursor = Cursor::new(buffer);
-
-    let start_addr = addrs[0];
-    for addr in addrs {
-        let a = addr - start_addr; 
-        xbh_cursor.seek(SeekFrom::Start(a)).unwrap();
-
-        let xbh: Result<Xbh, binread::Error> = xbh_cursor.read_ne();
-        if xbh.is_err() {
-            break;
-        }
-        let xbh = xbh.unwrap();
+    for xbh in read_headers(source, &addrs, &profile) {
+        let xbh = match xbh {
+            Some(xbh) => xbh,
+            None => continue,
+        };
 
         if xbh.objd == objd {
             print!("X");
@@ -90,4 +81,166 @@ pub fn visualize_buffers(fname: String, objd: u32, pid: u32) {
             }
         }
     } 
-}
\ No newline at end of file
+}
+
+/// Writes the blocks of the buffers of objd to <objd>.dat, following ba of the listed headers.
+/// Every block gets a line in <objd>.log with the state of its buffer (current or CR) and whether it's dirty.
+/// Buffers reused for another block since the header was read are skipped.
+pub fn dump_buffers(fname: String, objd: u32, source: &mut dyn MemorySource, profiles: &[BhProfile], workdir: &str) {
+    let mut addrs = read_bh_addr(fname);
+    addrs.sort();
+    let profile = detect_profile(source, &addrs, profiles);
+    if profile.state.is_none() && !profile.derive_state {
+        println!("Buffer header profile {} has no state offset, current and CR buffers can't be told apart", profile.name);
+    }
+    let headers: Vec<(u64, Xbh)> = addrs.iter().zip(read_headers(source, &addrs, &profile))
+        .filter_map(|(addr, xbh)| xbh.filter(|x| x.objd == objd).map(|x| (*addr, x))).collect();
+
+    //newest SCN of every block among its buffers
+    let mut newest_scn: HashMap<u32, u64> = HashMap::new();
+    if profile.state.is_none() && profile.derive_state {
+        let mut block_head = [0_u8; 28];
+        for (_, xbh) in &headers {
+            match source.read_at(xbh.ba, &mut block_head) {
+                Ok(n) if n == block_head.len() && field_u32(&block_head, 4) == xbh.rdba && field_u32(&block_head, 24) == xbh.objd => (),
+                _ => continue,
+            }
+            let scn = newest_scn.entry(xbh.rdba).or_default();
+            *scn = (*scn).max(watch::block_scn(&block_head));
+        }
+    }
+
+    let mut f_dat = File::options().append(true).create(true).open(format!("{}/{}.dat", workdir, objd)).unwrap();
+    let mut f_log = File::options().append(true).create(true).open(format!("{}/{}.log", workdir, objd)).unwrap();
+    let mut block_data = vec![0_u8; BLOCK_SIZE as usize];
+    let mut states: HashMap<String, usize> = HashMap::new();
+    let (mut dumped, mut skipped) = (0, 0);
+    for (addr, xbh) in headers {
+        match source.read_at(xbh.ba, &mut block_data) {
+            Ok(n) if n == block_data.len() && field_u32(&block_data, 4) == xbh.rdba && field_u32(&block_data, 24) == xbh.objd => (),
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        }
+
+        //a block changed since its SCNs were compared is still the newest copy
+        let newest = watch::block_scn(&block_data) >= newest_scn.get(&xbh.rdba).copied().unwrap_or(0);
+        let (state, dirty) = (profile.buffer_state(&xbh, newest), profile.buffer_dirty(&xbh));
+        f_dat.write_all(&block_data).unwrap();
+        f_log.write_all(format!("Block: {} found at offset: {} buffer header: {:#x} {} {}\n", xbh.rdba, xbh.ba, addr, state, dirty).as_bytes()).unwrap();
+        *states.entry(format!("{} {}", state, dirty)).or_default() += 1;
+        dumped += 1;
+    }
+
+    let mut summary: Vec<String> = states.iter().map(|(k, v)| format!("{} {}", v, k)).collect();
+    summary.sort();
+    let report = format!("Dumped {} buffers of object {} ({}), {} buffers no longer holding their block skipped", dumped, objd, summary.join(", "), skipped);
+    println!("{}", report);
+    block_organizer::write_log(workdir.to_string(), report);
+}
+
+fn field_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+//a header candidate: ba is block aligned and inside one of the regions
+fn is_candidate(xbh: &Xbh, regions: &[MemoryRegion]) -> bool {
+    if xbh.ba == 0 || !xbh.ba.is_multiple_of(BLOCK_SIZE) || xbh.rdba == 0 {
+        return false;
+    }
+    regions.iter().any(|r| xbh.ba >= r.start && xbh.ba + BLOCK_SIZE <= r.start + r.size)
+}
+
+/// A header matches when the block at ba has the rdba and the objd of the header
+fn points_to_block(source: &mut dyn MemorySource, xbh: &Xbh) -> bool {
+    let mut block_head = [0_u8; 28];
+    match source.read_at(xbh.ba, &mut block_head) {
+        Ok(n) if n == block_head.len() => (),
+        _ => return false,
+    }
+    field_u32(&block_head, 4) == xbh.rdba && field_u32(&block_head, 24) == xbh.objd
+}
+
+//header candidates (address, profile, header) starting in the first scan_len bytes of window, which holds whole headers from there
+fn scan_window(profiles: &[BhProfile], regions: &[MemoryRegion], start: u64, window: &[u8], scan_len: usize, candidates: &mut Vec<(u64, usize, Xbh)>) {
+    let step = profiles.iter().map(|p| p.align).min().unwrap_or(1);
+    let mut offset = (start.next_multiple_of(step as u64) - start) as usize;
+    while offset < scan_len {
+        let addr = start + offset as u64;
+        for (i, profile) in profiles.iter().enumerate() {
+            if offset + profile.size > window.len() || !addr.is_multiple_of(profile.align as u64) {
+                continue;
+            }
+            let xbh = profile.parse(&window[offset..offset + profile.size]);
+            if is_candidate(&xbh, regions) {
+                candidates.push((addr, i, xbh));
+            }
+        }
+        offset += step;
+    }
+}
+
+/// Scans the regions for structures laid out like a buffer header of one of the profiles, whose ba points to the block
+/// they describe. Addresses of the headers found with the best matching profile go to fname, in the format read by visualize_buffers.
+pub fn find_buffer_headers(source: &mut dyn MemorySource, regions: &[MemoryRegion], profiles: &[BhProfile], fname: &str, workdir: &str, skip_granularity: u64) -> usize {
+    let mut found: Vec<Vec<u64>> = vec![Vec::new(); profiles.len()];
+    let mut objds: Vec<HashMap<u32, usize>> = vec![HashMap::new(); profiles.len()];
+    let max_size = profiles.iter().map(|p| p.size).max().unwrap_or(0);
+
+    for region in regions {
+        let report = format!("Scanning {} from {:#x} to {:#x} for buffer headers", region.name, region.start, region.start + region.size);
+        println!("{}", report);
+        block_organizer::write_log(workdir.to_string(), report);
+
+        //the end of a chunk is kept until the next one, so headers crossing chunks are found
+        let mut tail: (u64, Vec<u8>) = (region.start, Vec::new());
+        let end = region.start + region.size;
+        let mut range_start = region.start;
+        while range_start < end {
+            let range = MemoryRegion { start: range_start, size: SCAN_RANGE.min(end - range_start), ..region.clone() };
+            let mut candidates: Vec<(u64, usize, Xbh)> = Vec::new();
+            memory_source::read_region(source, &range, skip_granularity, |addr, chunk_bytes| {
+                let (start, window) = if tail.0 + tail.1.len() as u64 == addr {
+                    (tail.0, [std::mem::take(&mut tail.1), chunk_bytes].concat())
+                } else {
+                    scan_window(profiles, regions, tail.0, &tail.1, tail.1.len(), &mut candidates);
+                    (addr, chunk_bytes)
+                };
+                let scan_len = window.len().saturating_sub(max_size.saturating_sub(1));
+                scan_window(profiles, regions, start, &window, scan_len, &mut candidates);
+                tail = (start + scan_len as u64, window[scan_len..].to_vec());
+            });
+            if range_start + range.size == end {
+                scan_window(profiles, regions, tail.0, &tail.1, tail.1.len(), &mut candidates);
+            }
+
+            for (addr, i, xbh) in candidates {
+                if points_to_block(source, &xbh) {
+                    found[i].push(addr);
+                    *objds[i].entry(xbh.objd).or_default() += 1;
+                }
+            }
+            range_start += range.size;
+        }
+    }
+
+    let mut best = 0;
+    for (i, profile) in profiles.iter().enumerate() {
+        if profiles.len() > 1 {
+            println!("Buffer header profile {}: {} headers of {} objects", profile.name, found[i].len(), objds[i].len());
+        }
+        if found[i].len() > found[best].len() {
+            best = i;
+        }
+    }
+
+    let mut f_addr = File::create(fname).unwrap();
+    for addr in &found[best] {
+        f_addr.write_all(format!("{:x}\n", addr).as_bytes()).unwrap();
+    }
+    let report = format!("Found {} buffer headers of {} objects with profile {}, addresses written to {}", found[best].len(), objds[best].len(), profiles[best].name, fname);
+    println!("{}", report);
+    block_organizer::write_log(workdir.to_string(), report);
+    found[best].len()
+}
@@ -0,0 +1,99 @@
+use encoding_rs::Encoding;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharsetKind {
+    Ascii,
+    Latin1,
+    Encoding(&'static Encoding),
+}
+
+/// Database (or national) character set used to turn VARCHAR2/CHAR and NVARCHAR2/NCHAR bytes into UTF-8
+#[derive(Debug, Clone)]
+pub struct Charset {
+    pub name: String,
+    kind: CharsetKind,
+}
+
+impl Default for Charset {
+    fn default() -> Self {
+        Charset { name: "AL32UTF8".to_string(), kind: CharsetKind::Encoding(encoding_rs::UTF_8) }
+    }
+}
+
+impl Charset {
+    pub fn national() -> Self {
+        Charset { name: "AL16UTF16".to_string(), kind: CharsetKind::Encoding(encoding_rs::UTF_16BE) }
+    }
+
+    pub fn is_utf16(&self) -> bool {
+        self.kind == CharsetKind::Encoding(encoding_rs::UTF_16BE)
+    }
+
+    /// ASCII, ISO-8859 and windows code pages, where every byte is a character
+    pub fn is_single_byte(&self) -> bool {
+        match self.kind {
+            CharsetKind::Ascii | CharsetKind::Latin1 => true,
+            CharsetKind::Encoding(e) => e.is_single_byte(),
+        }
+    }
+
+    /// Accepts Oracle names (NLS_CHARACTERSET) as well as WHATWG labels like "windows-1250"
+    pub fn from_name(name: &str) -> Result<Charset, String> {
+        let oracle_name = name.trim().to_uppercase();
+        let kind = match oracle_name.as_str() {
+            "AL32UTF8" | "UTF8" => CharsetKind::Encoding(encoding_rs::UTF_8),
+            "AL16UTF16" => CharsetKind::Encoding(encoding_rs::UTF_16BE),
+            "US7ASCII" => CharsetKind::Ascii,
+            "WE8ISO8859P1" => CharsetKind::Latin1,
+            "EE8MSWIN1250" => CharsetKind::Encoding(encoding_rs::WINDOWS_1250),
+            "CL8MSWIN1251" => CharsetKind::Encoding(encoding_rs::WINDOWS_1251),
+            "WE8MSWIN1252" => CharsetKind::Encoding(encoding_rs::WINDOWS_1252),
+            "EL8MSWIN1253" => CharsetKind::Encoding(encoding_rs::WINDOWS_1253),
+            "TR8MSWIN1254" => CharsetKind::Encoding(encoding_rs::WINDOWS_1254),
+            "IW8MSWIN1255" => CharsetKind::Encoding(encoding_rs::WINDOWS_1255),
+            "AR8MSWIN1256" => CharsetKind::Encoding(encoding_rs::WINDOWS_1256),
+            "BLT8MSWIN1257" => CharsetKind::Encoding(encoding_rs::WINDOWS_1257),
+            "VN8MSWIN1258" => CharsetKind::Encoding(encoding_rs::WINDOWS_1258),
+            "EE8ISO8859P2" => CharsetKind::Encoding(encoding_rs::ISO_8859_2),
+            "SE8ISO8859P3" => CharsetKind::Encoding(encoding_rs::ISO_8859_3),
+            "NEE8ISO8859P4" => CharsetKind::Encoding(encoding_rs::ISO_8859_4),
+            "CL8ISO8859P5" => CharsetKind::Encoding(encoding_rs::ISO_8859_5),
+            "AR8ISO8859P6" => CharsetKind::Encoding(encoding_rs::ISO_8859_6),
+            "EL8ISO8859P7" => CharsetKind::Encoding(encoding_rs::ISO_8859_7),
+            "IW8ISO8859P8" => CharsetKind::Encoding(encoding_rs::ISO_8859_8),
+            "WE8ISO8859P15" => CharsetKind::Encoding(encoding_rs::ISO_8859_15),
+            "CL8KOI8R" => CharsetKind::Encoding(encoding_rs::KOI8_R),
+            "TH8TISASCII" => CharsetKind::Encoding(encoding_rs::WINDOWS_874),
+            "ZHS16GBK" => CharsetKind::Encoding(encoding_rs::GBK),
+            "ZHS32GB18030" => CharsetKind::Encoding(encoding_rs::GB18030),
+            "ZHT16BIG5" | "ZHT16MSWIN950" | "ZHT16HKSCS" => CharsetKind::Encoding(encoding_rs::BIG5),
+            "JA16SJIS" | "JA16SJISTILDE" => CharsetKind::Encoding(encoding_rs::SHIFT_JIS),
+            "JA16EUC" | "JA16EUCTILDE" => CharsetKind::Encoding(encoding_rs::EUC_JP),
+            "KO16MSWIN949" | "KO16KSC5601" => CharsetKind::Encoding(encoding_rs::EUC_KR),
+            _ => match Encoding::for_label(name.trim().as_bytes()) {
+                Some(e) => CharsetKind::Encoding(e),
+                None => return Err(format!("Unsupported character set {}", name)),
+            },
+        };
+
+        Ok(Charset { name: oracle_name, kind })
+    }
+
+    /// Returns None when the bytes are not a valid sequence in this character set
+    pub fn decode(&self, bytes_val: &[u8]) -> Option<String> {
+        match self.kind {
+            CharsetKind::Ascii => {
+                if bytes_val.is_ascii() {
+                    Some(bytes_val.iter().map(|b| *b as char).collect())
+                } else {
+                    None
+                }
+            }
+            //encoding_rs treats ISO-8859-1 as windows-1252, Oracle does not
+            CharsetKind::Latin1 => Some(bytes_val.iter().map(|b| *b as char).collect()),
+            CharsetKind::Encoding(e) => {
+                e.decode_without_bom_handling_and_without_replacement(bytes_val).map(|s| s.into_owned())
+            }
+        }
+    }
+}
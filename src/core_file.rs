@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use binread::BinRead;
+use binread::BinReaderExt;
+use crate::memory_source::{MemoryRegion, MemorySource, RegionKind};
+use crate::memory_source;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+//NT_FILE note lists the files mapped by the process
+const NT_FILE: u32 = 0x4649_4c45;
+
+#[derive(BinRead)]
+#[derive(Debug)]
+#[allow(dead_code)]
+struct Elf64Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[derive(BinRead)]
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+#[derive(BinRead)]
+#[derive(Debug)]
+struct Elf64Nhdr {
+    n_namesz: u32,
+    n_descsz: u32,
+    n_type: u32,
+}
+
+struct CoreSegment {
+    vaddr: u64,
+    offset: u64,
+    filesz: u64,
+    perms: String,
+    name: String,
+}
+
+/// ELF core file (core dump or gcore) - PT_LOAD segments are the memory of the process at their virtual addresses.
+/// Parts of segments that were not dumped (memsz above filesz) can't be read - shared memory is dumped only
+/// when coredump_filter of the process allows it.
+pub struct CoreFile {
+    pub path: String,
+    f: File,
+    segments: Vec<CoreSegment>,
+}
+
+fn perms(p_flags: u32) -> String {
+    format!("{}{}{}", if p_flags & 4 != 0 { "r" } else { "-" }, if p_flags & 2 != 0 { "w" } else { "-" }, if p_flags & 1 != 0 { "x" } else { "-" })
+}
+
+fn align4(n: u64) -> u64 {
+    n.div_ceil(4) * 4
+}
+
+//NT_FILE descriptor: count(8) page size(8), count entries of start(8) end(8) file offset(8), then the file names.
+//None when the counts don't fit in the descriptor.
+fn nt_file_entries(desc: &[u8]) -> Option<Vec<(u64, u64, String)>> {
+    let count = usize::try_from(u64::from_le_bytes(desc.get(0..8)?.try_into().unwrap())).ok()?;
+    let names_start = count.checked_mul(24)?.checked_add(16)?;
+    let mut names = desc.get(names_start..)?.split(|b| *b == 0);
+    let mut files: Vec<(u64, u64, String)> = Vec::new();
+    for entry in desc[16..names_start].chunks_exact(24) {
+        let start = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+        let end = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+        let name = names.next().map(|n| String::from_utf8_lossy(n).to_string()).unwrap_or_default();
+        files.push((start, end, name));
+    }
+    Some(files)
+}
+
+//(start, end, file name) of mapped files from the NT_FILE note
+fn mapped_files(note: &[u8]) -> Vec<(u64, u64, String)> {
+    let mut files: Vec<(u64, u64, String)> = Vec::new();
+    let mut cursor = Cursor::new(note);
+    while (cursor.position() as usize) + 12 <= note.len() {
+        let nhdr: Elf64Nhdr = match cursor.read_le() {
+            Ok(n) => n,
+            Err(_) => break,
+        };
+        let name_end = cursor.position() + align4(nhdr.n_namesz as u64);
+        let desc_start = name_end as usize;
+        let desc_end = desc_start + nhdr.n_descsz as usize;
+        if desc_end > note.len() {
+            break;
+        }
+
+        if nhdr.n_type == NT_FILE {
+            files.extend(nt_file_entries(&note[desc_start..desc_end]).unwrap_or_default());
+        }
+        cursor.set_position(align4(desc_end as u64));
+    }
+    files
+}
+
+impl CoreFile {
+    pub fn open(path: &str) -> Result<CoreFile, String> {
+        let mut f = File::open(path).map_err(|e| format!("Can't open core file {}: {}", path, e))?;
+        let ehdr: Elf64Ehdr = f.read_le().map_err(|e| e.to_string())?;
+        if ehdr.e_ident[0..4] != ELF_MAGIC || ehdr.e_ident[4] != ELFCLASS64 || ehdr.e_type != ET_CORE {
+            return Err(format!("{} is not a 64-bit ELF core file", path));
+        }
+
+        let mut phdrs: Vec<Elf64Phdr> = Vec::new();
+        for i in 0..ehdr.e_phnum as u64 {
+            f.seek(SeekFrom::Start(ehdr.e_phoff + i * ehdr.e_phentsize as u64)).map_err(|e| e.to_string())?;
+            phdrs.push(f.read_le().map_err(|e| e.to_string())?);
+        }
+
+        let mut files: Vec<(u64, u64, String)> = Vec::new();
+        for note in phdrs.iter().filter(|p| p.p_type == PT_NOTE) {
+            let mut note_data = vec![0_u8; note.p_filesz as usize];
+            f.seek(SeekFrom::Start(note.p_offset)).map_err(|e| e.to_string())?;
+            f.read_exact(&mut note_data).map_err(|e| e.to_string())?;
+            files.extend(mapped_files(&note_data));
+        }
+
+        let segments = phdrs.iter().filter(|p| p.p_type == PT_LOAD).map(|p| CoreSegment {
+            vaddr: p.p_vaddr,
+            offset: p.p_offset,
+            filesz: p.p_filesz,
+            perms: perms(p.p_flags),
+            name: files.iter().find(|(start, end, _)| p.p_vaddr >= *start && p.p_vaddr < *end).map(|f| f.2.clone()).unwrap_or_default(),
+        }).collect();
+
+        Ok(CoreFile { path: path.to_string(), f, segments })
+    }
+}
+
+impl MemorySource for CoreFile {
+    /// Dumped segments, named after the mapped file when the core has an NT_FILE note
+    fn regions(&self) -> Vec<MemoryRegion> {
+        self.segments.iter().filter(|s| s.filesz > 0).map(|s| MemoryRegion {
+            start: s.vaddr,
+            size: s.filesz,
+            perms: s.perms.clone(),
+            name: if s.name.is_empty() { format!("{} segment", self.path) } else { s.name.clone() },
+            kind: memory_source::region_kind(&s.name).unwrap_or(RegionKind::Other),
+            score: 0,
+        }).collect()
+    }
+
+    fn read_at(&mut self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let segment = self.segments.iter().find(|s| addr >= s.vaddr && addr < s.vaddr + s.filesz)
+            .ok_or(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Address not in the core file"))?;
+        let len = buf.len().min((segment.vaddr + segment.filesz - addr) as usize);
+        self.f.seek(SeekFrom::Start(segment.offset + addr - segment.vaddr))?;
+        self.f.read(&mut buf[..len])
+    }
+
+    fn reader(&self) -> Option<Box<dyn MemorySource + Send>> {
+        CoreFile::open(&self.path).ok().map(|c| Box::new(c) as Box<dyn MemorySource + Send>)
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ColumnDef {
+    pub name: String,
+    pub data_type: String,
+    /// Nested table columns: data object id of the storage table (dba_nested_tables.storage_table).
+    /// Its rows are linked to the parent rows when the storage table is in the dictionary with its NESTED_TABLE_ID column.
+    #[serde(default)]
+    pub storage_table: Option<u32>,
+}
+
+/// User-defined type, keyed by type name in the dictionary.
+/// OBJECT types list attributes in order (dba_type_attrs order by attr_no),
+/// VARRAY and TABLE types give the element type (dba_coll_types.elem_type_name).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TypeDef {
+    pub typecode: String,
+    #[serde(default)]
+    pub attributes: Vec<ColumnDef>,
+    pub element_type: Option<String>,
+}
+
+impl TypeDef {
+    pub fn is_object(&self) -> bool {
+        self.typecode.eq_ignore_ascii_case("OBJECT")
+    }
+
+    pub fn is_varray(&self) -> bool {
+        self.typecode.eq_ignore_ascii_case("VARRAY") || self.typecode.eq_ignore_ascii_case("VARYING ARRAY")
+    }
+
+    pub fn is_nested_table(&self) -> bool {
+        self.typecode.eq_ignore_ascii_case("TABLE")
+    }
+}
+
+/// Table definition - columns in segment order (select column_name, data_type from dba_tab_cols order by segment_column_id).
+/// Object columns are followed by the hidden columns of their attributes, as in the segment.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TableDef {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+}
+
+/// Dictionary information of the source database, stored as JSON.
+/// Character sets can be taken from:
+/// select parameter, value from nls_database_parameters where parameter like 'NLS_%CHARACTERSET';
+/// and the database time zone from: select dbtimezone from dual;
+/// Tables are keyed by data object id (dba_objects.data_object_id).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Dictionary {
+    pub nls_characterset: Option<String>,
+    pub nls_nchar_characterset: Option<String>,
+    pub dbtimezone: Option<String>,
+    /// Additional time zone region IDs: {"<region id>": "<zone name>"}
+    #[serde(default)]
+    pub timezone_regions: HashMap<String, String>,
+    #[serde(default)]
+    pub objects: HashMap<String, TableDef>,
+    #[serde(default)]
+    pub types: HashMap<String, TypeDef>,
+}
+
+impl Dictionary {
+    pub fn table(&self, objd: u32) -> Option<&TableDef> {
+        self.objects.get(&objd.to_string())
+    }
+
+    pub fn type_def(&self, type_name: &str) -> Option<&TypeDef> {
+        self.types.get(type_name).or_else(|| self.types.get(&type_name.trim().to_uppercase()))
+    }
+
+    /// Storage table of a nested table column
+    pub fn storage_table(&self, column: &ColumnDef) -> Option<u32> {
+        self.type_def(&column.data_type).filter(|t| t.is_nested_table()).and(column.storage_table)
+    }
+
+    /// Position of the hidden NESTED_TABLE_ID column of a storage table, the set ID its rows belong to
+    pub fn nested_table_id_column(&self, objd: u32) -> Option<usize> {
+        self.table(objd)?.columns.iter().position(|c| c.name.eq_ignore_ascii_case("NESTED_TABLE_ID"))
+    }
+}
+
+pub fn read_dictionary(fname: &str) -> Dictionary {
+    let dict_file = fs::read_to_string(fname).unwrap_or_else(|_| panic!("Something wrong with a file {} ", fname));
+    let dictionary: Dictionary = serde_json::from_str(&dict_file).expect("Wrong JSON format of a dictionary");
+    dictionary
+}
@@ -0,0 +1,102 @@
+use crate::oracle_decoder;
+use crate::oracle_decoder::{DecodeOptions, OracleType};
+use crate::charset::Charset;
+use crate::oson_decoder;
+
+const LOB_LOCATOR_HEADER: usize = 20;
+const LOB_FLAG_BLOB: u8 = 0x01;
+const LOB_FLAG_VARYING_WIDTH: u8 = 0x80;
+const LOB_FLAG_SECUREFILE: u8 = 0x80;
+const INODE_FLAG_DATA_IN_ROW: u8 = 0x08;
+const BASICFILE_INODE_HEADER: usize = 16;
+const SECUREFILE_INODE_HEADER: usize = 10;
+
+/// LOB locator as stored in the row:
+/// length(2) version(2) flags(4) byte length(2) LOB ID(10), followed by the inode with inline data when the LOB is stored in row
+#[derive(Debug, Clone)]
+pub struct LobLocator {
+    pub flags: [u8; 4],
+    pub byte_length: u16,
+    pub lob_id: [u8; 10],
+    pub inode: Vec<u8>,
+}
+
+impl LobLocator {
+    pub fn is_blob(&self) -> bool {
+        self.flags[0] & LOB_FLAG_BLOB != 0
+    }
+
+    //CLOBs in multibyte databases (and NCLOBs) are stored in AL16UTF16
+    pub fn is_varying_width(&self) -> bool {
+        self.flags[2] & LOB_FLAG_VARYING_WIDTH != 0
+    }
+
+    pub fn is_securefile(&self) -> bool {
+        self.flags[3] & LOB_FLAG_SECUREFILE != 0
+    }
+
+    pub fn lob_id_hex(&self) -> String {
+        hex::encode(self.lob_id)
+    }
+
+    pub fn is_in_row(&self) -> bool {
+        self.inode.len() > 2 && self.inode[2] & INODE_FLAG_DATA_IN_ROW != 0
+    }
+
+    /// BasicFile: size(2) flag(1) future(1) length and version(12) data
+    /// SecureFile: size(2) flag(1) flag0(1) lengths array(2) inode header(4) data
+    pub fn inline_data(&self) -> Option<&[u8]> {
+        if !self.is_in_row() {
+            return None;
+        }
+        let inode_size = u16::from_be_bytes(self.inode[0..2].try_into().unwrap()) as usize;
+        let inode_end = (2 + inode_size).min(self.inode.len());
+
+        let data_start = if self.is_securefile() { SECUREFILE_INODE_HEADER } else { BASICFILE_INODE_HEADER };
+        self.inode.get(data_start..inode_end)
+    }
+}
+
+pub fn is_lob_locator(bytes_val: &[u8]) -> bool {
+    bytes_val.len() >= LOB_LOCATOR_HEADER && bytes_val[0..2] == [0x00, 0x54] && bytes_val[2..4] == [0x00, 0x01]
+}
+
+pub fn parse_locator(bytes_val: &[u8]) -> Result<LobLocator, String> {
+    if !is_lob_locator(bytes_val) {
+        return Err("Not a LOB locator".to_string());
+    }
+
+    Ok(LobLocator {
+        flags: bytes_val[4..8].try_into().unwrap(),
+        byte_length: u16::from_be_bytes(bytes_val[8..10].try_into().unwrap()),
+        lob_id: bytes_val[10..20].try_into().unwrap(),
+        inode: bytes_val[LOB_LOCATOR_HEADER..].to_vec(),
+    })
+}
+
+/// Inline LOBs are decoded according to the column type (JSON, VECTOR or CLOB/NCLOB/BLOB),
+/// out-of-line LOBs are reported by their LOB ID
+pub fn check_lob(bytes_val: Vec<u8>, data_type: Option<&str>, options: &DecodeOptions) -> Result<OracleType, String> {
+    let locator = parse_locator(&bytes_val)?;
+    let lob_type = match data_type {
+        Some(t) => t.to_string(),
+        None if locator.is_blob() => "BLOB".to_string(),
+        None => "CLOB".to_string(),
+    };
+
+    let inline = match locator.inline_data() {
+        Some(inline) => inline.to_vec(),
+        None => return Ok(OracleType{data_type: lob_type, value: format!("LOBID:{}", locator.lob_id_hex())}),
+    };
+
+    let value = match lob_type.as_str() {
+        "JSON" => oson_decoder::check_oson(inline, options)?.value,
+        "VECTOR" => oracle_decoder::check_vector(inline)?.value,
+        "BLOB" if oson_decoder::is_oson(&inline) => oson_decoder::check_oson(inline, options)?.value,
+        "BLOB" => hex::encode(inline),
+        _ if locator.is_varying_width() || lob_type == "NCLOB" => oracle_decoder::check_nstring(inline, &Charset::national())?.value,
+        _ => oracle_decoder::check_string(inline, &options.charset)?.value,
+    };
+
+    Ok(OracleType{data_type: lob_type, value})
+}
@@ -0,0 +1,270 @@
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use crate::block_organizer::BLOCK_SIZE;
+use crate::charset::Charset;
+use crate::lob_locator::LobLocator;
+use crate::memory_source;
+use crate::oracle_decoder::DecodeOptions;
+
+/// PAGETABLE MANAGED LOB BLOCK - chunks of out-of-line LOBs
+pub const LOB_BLOCK_TYPE: u8 = 0x28;
+//LOB ID, page number, version and pdba precede the data, 8132 bytes of data fit into an 8K block
+const LOB_BLOCK_HEADER: usize = 56;
+const LOB_BLOCK_TAIL: usize = 4;
+const LOB_CHUNK_SIZE: u64 = BLOCK_SIZE - LOB_BLOCK_HEADER as u64 - LOB_BLOCK_TAIL as u64;
+//the LOB ID and page number are looked up in this part of a LOB block
+const LOB_ID_SEARCH_LEN: usize = 120;
+const LOB_ID_LEN: usize = 10;
+
+pub fn is_lob_block(block_data: &[u8]) -> bool {
+    block_data.len() == BLOCK_SIZE as usize && block_data[0] == LOB_BLOCK_TYPE && memory_source::looks_like_block(block_data)
+        && block_data[4..8] != [0, 0, 0, 0]
+}
+
+/// LOB index blocks - index blocks (ktbbhtyp 2) with a row keyed by LOB ID and page number, kept as <objd>.idx.dat
+pub fn is_lob_index_block(block_data: &[u8]) -> bool {
+    block_data[0] == 6 && block_data[20] == 2 && (0..block_data.len()).any(|p| lob_index_row(&block_data[p..]).is_some())
+}
+
+//LOB index leaf row at the start of key: 10, LOB ID, 4, page number, length of the DBAs (4 to 32 bytes), DBAs.
+//Other indexes end their keys with a 6 byte rowid.
+fn lob_index_row(key: &[u8]) -> Option<([u8; LOB_ID_LEN], u32, &[u8])> {
+    if key.len() < 7 + LOB_ID_LEN || key[0] as usize != LOB_ID_LEN || key[1 + LOB_ID_LEN] != 4 {
+        return None;
+    }
+    let dbas_len = key[6 + LOB_ID_LEN] as usize;
+    if dbas_len == 0 || dbas_len > 32 || !dbas_len.is_multiple_of(4) {
+        return None;
+    }
+    let dbas = key.get(7 + LOB_ID_LEN..7 + LOB_ID_LEN + dbas_len)?;
+    if dbas[0..4] == [0, 0, 0, 0] {
+        return None;
+    }
+    let lob_id: [u8; LOB_ID_LEN] = key[1..1 + LOB_ID_LEN].try_into().unwrap();
+    let page_no = u32::from_be_bytes(key[2 + LOB_ID_LEN..6 + LOB_ID_LEN].try_into().unwrap());
+    Some((lob_id, page_no, dbas))
+}
+
+pub fn lob_file_name(locator: &LobLocator) -> String {
+    let extension = if locator.is_blob() { "bin" } else { "txt" };
+    format!("lobs/{}.{}", locator.lob_id_hex(), extension)
+}
+
+/// Out-of-line LOBs found during extraction are queued in lobs.todo and rebuilt when the extraction ends
+pub fn queue_lob(workdir: &str, locator: &LobLocator, raw_locator: &[u8]) {
+    let line = format!("{}|{}\n", locator.lob_id_hex(), hex::encode(raw_locator));
+    let mut f_obj = File::options().append(true).create(true).open(format!("{}/lobs.todo", workdir)).unwrap();
+    f_obj.write_all(line.as_bytes()).unwrap();
+}
+
+fn read_block(f: &mut File, offset: u64) -> Vec<u8> {
+    let mut block_data = vec![0_u8; BLOCK_SIZE as usize];
+    f.seek(SeekFrom::Start(offset)).unwrap();
+    f.read_exact(&mut block_data).unwrap();
+    block_data
+}
+
+/// BasicFile inode: size(2) flag(1) future(1) blocks(4) bytes in the last block(2) version(6) DBA array
+fn inode_layout(locator: &LobLocator) -> Option<(u64, Vec<u32>)> {
+    let inode = &locator.inode;
+    if inode.len() < 16 || locator.is_securefile() {
+        return None;
+    }
+    let blocks = u32::from_be_bytes(inode[4..8].try_into().unwrap()) as u64;
+    let last_bytes = u16::from_be_bytes(inode[8..10].try_into().unwrap()) as u64;
+    let dbas: Vec<u32> = inode[16..].chunks_exact(4).map(|d| u32::from_be_bytes(d.try_into().unwrap())).filter(|d| *d != 0).collect();
+    let length = if blocks > 0 { (blocks - 1) * LOB_CHUNK_SIZE + last_bytes } else { last_bytes };
+    Some((length, dbas))
+}
+
+/// Blocks of lobs.dat, indexed in one pass: offsets by rdba and (page, rdba, offset) of the queued LOBs by LOB ID
+struct LobBlocks {
+    by_rdba: HashMap<u32, Vec<u64>>,
+    pages: HashMap<[u8; LOB_ID_LEN], Vec<(u32, u32, u64)>>,
+}
+
+//the LOB ID is followed by the page number in the block header
+fn index_lob_blocks(f: &mut File, lob_ids: &HashSet<[u8; LOB_ID_LEN]>) -> LobBlocks {
+    let mut blocks = LobBlocks { by_rdba: HashMap::new(), pages: HashMap::new() };
+    let blocks_count = f.metadata().unwrap().len() / BLOCK_SIZE;
+    for i in 0..blocks_count {
+        let offset = i * BLOCK_SIZE;
+        let block_data = read_block(f, offset);
+        let rdba = u32::from_ne_bytes(block_data[4..8].try_into().unwrap());
+        blocks.by_rdba.entry(rdba).or_default().push(offset);
+
+        let found = block_data[20..LOB_ID_SEARCH_LEN].windows(LOB_ID_LEN).position(|w| lob_ids.contains(w));
+        if let Some(p) = found.map(|p| p + 20) {
+            let lob_id: [u8; LOB_ID_LEN] = block_data[p..p + LOB_ID_LEN].try_into().unwrap();
+            let page_no = u32::from_be_bytes(block_data[p + LOB_ID_LEN..p + LOB_ID_LEN + 4].try_into().unwrap());
+            blocks.pages.entry(lob_id).or_default().push((page_no, rdba, offset));
+        }
+    }
+    blocks
+}
+
+/// BasicFile LOB index leaf rows: LOB ID(10) and page number(4) key columns followed by the DBAs of up to 8 chunks.
+/// Old versions of a page stay in the LOB segment under the same LOB ID, the index points to the current one.
+fn read_lob_index(workdir: &str, lob_ids: &HashSet<[u8; LOB_ID_LEN]>) -> HashMap<[u8; LOB_ID_LEN], HashMap<u32, u32>> {
+    let mut index: HashMap<[u8; LOB_ID_LEN], HashMap<u32, u32>> = HashMap::new();
+    let idx_files = fs::read_dir(workdir).unwrap().flatten().map(|e| e.path()).filter(|p| p.to_string_lossy().ends_with(".idx.dat"));
+    for idx_file in idx_files {
+        let idx_data = fs::read(&idx_file).unwrap();
+        for block_data in idx_data.chunks_exact(BLOCK_SIZE as usize) {
+            let mut p = 0;
+            while p < block_data.len() {
+                let (lob_id, page_no, dbas) = match lob_index_row(&block_data[p..]) {
+                    Some(row) if lob_ids.contains(&row.0) => row,
+                    _ => {
+                        p += 1;
+                        continue;
+                    }
+                };
+                let pages = index.entry(lob_id).or_default();
+                for (i, dba) in dbas.chunks_exact(4).map(|d| u32::from_be_bytes(d.try_into().unwrap())).enumerate() {
+                    if dba != 0 {
+                        pages.insert(page_no + i as u32, dba);
+                    }
+                }
+                p += 7 + LOB_ID_LEN + dbas.len();
+            }
+        }
+    }
+    index
+}
+
+fn lob_chunks(f: &mut File, blocks: &LobBlocks, index: &HashMap<[u8; LOB_ID_LEN], HashMap<u32, u32>>, locator: &LobLocator) -> Vec<Vec<u8>> {
+    let mut chunks: Vec<Vec<u8>> = Vec::new();
+
+    //BasicFile inodes list the first chunks directly
+    if let Some((_, dbas)) = inode_layout(locator) {
+        let blocks_in_inode = u32::from_be_bytes(locator.inode[4..8].try_into().unwrap()) as usize;
+        if !dbas.is_empty() && dbas.len() >= blocks_in_inode {
+            for dba in dbas.iter().take(blocks_in_inode) {
+                match blocks.by_rdba.get(dba).and_then(|b| b.first()) {
+                    Some(offset) => chunks.push(read_block(f, *offset)[LOB_BLOCK_HEADER..(BLOCK_SIZE as usize - LOB_BLOCK_TAIL)].to_vec()),
+                    None => return Vec::new(),
+                }
+            }
+            return chunks;
+        }
+    }
+
+    //larger LOBs and SecureFiles are found by their LOB ID and ordered by page number,
+    //the version of a page the LOB index points to goes first
+    let mut pages = blocks.pages.get(&locator.lob_id).cloned().unwrap_or_default();
+    let index_pages = index.get(&locator.lob_id);
+    pages.sort_by_key(|(page_no, rdba, offset)| (*page_no, index_pages.and_then(|i| i.get(page_no)) != Some(rdba), *offset));
+    pages.dedup_by_key(|p| p.0);
+    for (_, _, offset) in pages {
+        chunks.push(read_block(f, offset)[LOB_BLOCK_HEADER..(BLOCK_SIZE as usize - LOB_BLOCK_TAIL)].to_vec());
+    }
+    chunks
+}
+
+//zlib header: deflate with a window up to 32K and a valid check value
+fn has_zlib_header(lob_data: &[u8]) -> bool {
+    lob_data.len() > 2 && lob_data[0] == 0x78 && ((lob_data[0] as u16) << 8 | lob_data[1] as u16).is_multiple_of(31)
+}
+
+/// Rebuilds queued out-of-line LOBs from lobs.dat (LOB blocks kept by consolidation) into lobs/<lob id>.txt|bin.
+/// The queue is consumed, so extracting again doesn't rebuild the LOBs of earlier runs.
+pub fn reassemble_lobs(workdir: &str, options: &DecodeOptions) {
+    let todo_file = format!("{}/lobs.todo", workdir);
+    let todo = match fs::read_to_string(&todo_file) {
+        Ok(todo) => todo,
+        Err(_) => return,
+    };
+    fs::remove_file(&todo_file).unwrap();
+
+    //deduplicated SecureFiles share a LOB ID - one file serves all rows
+    let mut locators: Vec<LobLocator> = Vec::new();
+    let mut lob_ids: HashSet<[u8; LOB_ID_LEN]> = HashSet::new();
+    for line in todo.lines() {
+        let raw_locator = match line.split_once('|') {
+            Some(l) => l.1,
+            None => continue,
+        };
+        let locator = crate::lob_locator::parse_locator(&hex::decode(raw_locator).unwrap()).unwrap();
+        if lob_ids.insert(locator.lob_id) {
+            locators.push(locator);
+        }
+    }
+
+    let lob_blocks_file = format!("{}/lobs.dat", workdir);
+    let mut f = match File::open(&lob_blocks_file) {
+        Ok(f) => f,
+        Err(_) => {
+            println!("No LOB blocks in {}, {} out-of-line LOBs can't be rebuilt", lob_blocks_file, locators.len());
+            return;
+        }
+    };
+    //the same block can be kept more than once (several copies in the source), the first copy is used
+    let blocks = index_lob_blocks(&mut f, &lob_ids);
+    let index = read_lob_index(workdir, &lob_ids);
+
+    fs::create_dir_all(format!("{}/lobs", workdir)).unwrap();
+    for locator in locators {
+        let lob_id = locator.lob_id_hex();
+        let chunks = lob_chunks(&mut f, &blocks, &index, &locator);
+        if chunks.is_empty() {
+            println!("LOB {} not rebuilt - no chunks found", lob_id);
+            continue;
+        }
+        let mut lob_data: Vec<u8> = chunks.concat();
+        if let Some((length, _)) = inode_layout(&locator) {
+            lob_data.truncate(length as usize);
+        }
+        if locator.is_securefile() && has_zlib_header(&lob_data) {
+            println!("LOB {} starts with a zlib header, it may be a compressed SecureFile - written as stored", lob_id);
+        }
+
+        let charset = if locator.is_varying_width() { Charset::national() } else { options.charset.clone() };
+        let lob_data = if locator.is_blob() {
+            lob_data
+        } else {
+            match charset.decode(&lob_data) {
+                Some(text) => text.into_bytes(),
+                None => {
+                    println!("LOB {} is not valid {} text, raw bytes written", lob_id, charset.name);
+                    lob_data
+                }
+            }
+        };
+
+        let mut f_lob = File::create(format!("{}/{}", workdir, lob_file_name(&locator))).unwrap();
+        f_lob.write_all(&lob_data).unwrap();
+        println!("LOB {} rebuilt from {} chunks", lob_id, chunks.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_block(row: &[u8]) -> Vec<u8> {
+        let mut block_data = vec![0_u8; BLOCK_SIZE as usize];
+        block_data[0] = 6;
+        block_data[20] = 2;
+        block_data[8000..8000 + row.len()].copy_from_slice(row);
+        block_data
+    }
+
+    #[test]
+    fn lob_index_blocks_only() {
+        let lob_id = [0, 0, 0, 1, 0, 0, 0, 0x2a, 0x4f, 0x11];
+        let lob_row = [&[2, 0, 10][..], &lob_id, &[4, 0, 0, 0, 0, 8, 0x01, 0x40, 0x00, 0x83, 0x01, 0x40, 0x00, 0x84]].concat();
+        assert_eq!(lob_index_row(&lob_row[2..]), Some((lob_id, 0, &lob_row[19..27])));
+        assert!(is_lob_index_block(&index_block(&lob_row)));
+
+        //VARCHAR2(10), NUMBER key and a rowid
+        let other_row = [&[0, 0, 10][..], b"ABCDEFGHIJ", &[4, 0xc3, 0x02, 0x03, 0x04, 6, 0x01, 0x40, 0x00, 0x83, 0x00, 0x01]].concat();
+        assert!(!is_lob_index_block(&index_block(&other_row)));
+    }
+}
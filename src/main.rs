@@ -3,10 +3,30 @@ use std::fs;
 use std::fs::File;
 use clap::Parser;
 use serde::{Deserialize, Serialize};
+use charset::Charset;
+use dictionary::Dictionary;
+use oracle_decoder::{BcFormat, DecodeOptions};
+use block_organizer::ExtractOptions;
+use raw_output::RawOutput;
+use memory_source::{MemoryRegion, MemorySource};
 
 mod block_organizer;
 mod oracle_decoder;
 mod buffer_organizer;
+mod charset;
+mod dictionary;
+mod timezone;
+mod raw_output;
+mod oson_decoder;
+mod lob_locator;
+mod lob_organizer;
+mod row_organizer;
+mod object_decoder;
+mod memory_source;
+mod core_file;
+mod snapshot;
+mod watch;
+mod bh_profile;
  
 /// Tool for extracting raw data from datafiles
 #[derive(Parser, Debug)]
@@ -23,6 +43,18 @@ struct Args {
     /// Number of parallel threads
     #[clap(short, long, default_value_t=2, short='P')]
     parallel: u8,
+
+    /// Database character set used for manual recognition
+    #[clap(short, long, default_value="AL32UTF8")]
+    charset: String,
+
+    /// National character set used for manual recognition
+    #[clap(short, long, default_value="AL16UTF16")]
+    ncharset: String,
+
+    /// Data type used for manual recognition
+    #[clap(short='t', long, default_value="GUESS")]
+    data_type: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -30,18 +62,94 @@ struct Params {
     action: String,
     workdir: String,
     data_files: Vec<String>,
+    charset: Option<String>,
+    ncharset: Option<String>,
+    dbtimezone: Option<String>,
+    timezone_regions: Option<String>,
+    dictionary: Option<String>,
+    binary_float_heuristic: Option<bool>,
+    raw_output: Option<String>,
+    long_inline_size: Option<usize>,
+    bc_format: Option<String>,
+    skip_granularity: Option<u64>,
+    readers: Option<u8>,
+    objds: Option<Vec<u32>>,
+    watch_interval: Option<u64>,
+    watch_passes: Option<u64>,
+    bh_profile: Option<String>,
 }
 
 fn read_params(fname: &str) -> Params {
-    let param_file = fs::read_to_string(fname).expect(&format!("Something wrong with a file {} ", fname));
+    let param_file = fs::read_to_string(fname).unwrap_or_else(|_| panic!("Something wrong with a file {} ", fname));
     let v_params: Params = serde_json::from_str(&param_file).expect("Wrong JSON format");
     v_params
 }
 
+fn decode_options(params: &Params) -> DecodeOptions {
+    let dictionary = match &params.dictionary {
+        Some(fname) => dictionary::read_dictionary(fname),
+        None => Dictionary::default(),
+    };
+
+    let charset_name = params.charset.clone().or(dictionary.nls_characterset.clone()).unwrap_or("AL32UTF8".to_string());
+    let charset = Charset::from_name(&charset_name).unwrap();
+    let ncharset_name = params.ncharset.clone().or(dictionary.nls_nchar_characterset.clone()).unwrap_or("AL16UTF16".to_string());
+    let ncharset = Charset::from_name(&ncharset_name).unwrap();
+
+    let dbtimezone = params.dbtimezone.clone().or(dictionary.dbtimezone.clone()).unwrap_or("+00:00".to_string());
+
+    let mut tz_regions = timezone::embedded_regions();
+    if let Some(fname) = &params.timezone_regions {
+        tz_regions.extend(timezone::read_regions(fname).unwrap());
+    }
+    for (id, name) in &dictionary.timezone_regions {
+        tz_regions.insert(id.parse().expect("Wrong time zone region ID in a dictionary"), name.clone());
+    }
+
+    let binary_float_heuristic = params.binary_float_heuristic.unwrap_or(false);
+    let bc_format = BcFormat::from_name(params.bc_format.as_deref().unwrap_or("minus")).unwrap();
+
+    DecodeOptions { charset, ncharset, dictionary, dbtimezone, tz_regions, binary_float_heuristic, bc_format }
+}
+
+//data_files[1]: auto (default) discovers the SGA, all takes every region of the source, a number is the exact size of a process map
+fn memory_regions(params: &Params, source: &mut dyn MemorySource) -> Vec<MemoryRegion> {
+    match params.data_files.get(1).map(|s| s.as_str()) {
+        None | Some("auto") => memory_source::discover_sga(source, &params.workdir),
+        Some("all") => source.regions(),
+        Some(memory_size) => {
+            let pid = memory_source::spec_pid(&params.data_files[0]).expect("Memory size can be given only for a pid");
+            memory_source::region_by_size(pid, memory_size.parse().unwrap()).into_iter().collect()
+        }
+    }
+}
+
+//exits with the error when the profiles can't be loaded
+fn buffer_header_profiles(params: &Params) -> Vec<bh_profile::BhProfile> {
+    bh_profile::load_profiles(params.bh_profile.as_deref()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+//buffer headers point to addresses of the Oracle process, exits when the source is read at other addresses
+fn buffer_source(spec: &str) -> Box<dyn MemorySource> {
+    let source = memory_source::open_source(spec);
+    if !source.process_addresses() {
+        eprintln!("Buffer headers point to addresses of the Oracle process, {} isn't read at them - use <pid>, vm:<pid>, core:<file> or a snapshot of one of them", spec);
+        std::process::exit(1);
+    }
+    source
+}
+
 fn main() {
     let args = Args::parse(); 
     if args.manual_string == "NO" {
         let params = read_params(&args.param_file);
+        let options = decode_options(&params);
+        let raw_output = RawOutput::from_name(params.raw_output.as_deref().unwrap_or("none")).unwrap();
+        let long_inline_size = params.long_inline_size.unwrap_or(4000);
+        let extract_options = ExtractOptions { decode: options.clone(), raw_output, long_inline_size };
         
         if params.action == "consolidate objects" {
             for f in params.data_files {
@@ -49,20 +157,62 @@ fn main() {
             }
         } else if params.action == "extract data from file" {
             for f in params.data_files {
-                block_organizer::extract_from_file(f, params.workdir.clone(), args.parallel);
+                block_organizer::extract_from_file(f, params.workdir.clone(), args.parallel, extract_options.clone());
+            }
+            block_organizer::finish_extraction(&params.workdir, &extract_options);
+        } else if params.action == "redecode extraction" {
+            for f in params.data_files {
+                raw_output::redecode_file(f, params.workdir.clone(), options.clone());
             }
         } else if params.action == "consolidate objects from memory" {
-            let pid: u32 = params.data_files[0].parse().unwrap();
-            let memory_size: u64 = params.data_files[1].parse().unwrap();
-            block_organizer::consolidate_objects_from_memory(pid, memory_size, params.workdir.clone(), args.parallel);
+            let mut source = memory_source::open_source(&params.data_files[0]);
+            let regions = memory_regions(&params, source.as_mut());
+            if regions.is_empty() {
+                println!("No SGA memory found in {}", params.data_files[0]);
+            } else {
+                block_organizer::consolidate_objects_from_memory(source.as_mut(), regions, params.workdir.clone(), args.parallel, params.readers.unwrap_or(1), params.skip_granularity.unwrap_or(4096));
+            }
+        } else if params.action == "watch memory" {
+            let watch_options = watch::WatchOptions {
+                objds: params.objds.clone().expect("Watch needs objds"),
+                interval: std::time::Duration::from_secs(params.watch_interval.unwrap_or(5)),
+                passes: params.watch_passes,
+            };
+            let mut source = memory_source::open_source(&params.data_files[0]);
+            let regions = memory_regions(&params, source.as_mut());
+            watch::watch_objects_from_memory(source.as_mut(), regions, params.workdir.clone(), &watch_options, params.readers.unwrap_or(1), params.skip_granularity.unwrap_or(4096));
+        } else if params.action == "benchmark memory read" {
+            let pid = memory_source::spec_pid(&params.data_files[0]).expect("Benchmark needs a pid");
+            let mut source = memory_source::open_source(&params.data_files[0]);
+            let regions = memory_regions(&params, source.as_mut());
+            memory_source::benchmark(pid, &regions, params.readers.unwrap_or(args.parallel), params.skip_granularity.unwrap_or(4096));
+        } else if params.action == "snapshot" {
+            let mut source = memory_source::open_source(&params.data_files[0]);
+            let regions = memory_regions(&params, source.as_mut());
+            let path = params.data_files.get(2).cloned().unwrap_or_else(|| format!("{}/{}.snap", params.workdir, chrono::Utc::now().format("%Y%m%d%H%M%S")));
+            snapshot::write_snapshot(source.as_mut(), &regions, &params.data_files[0], &path, params.skip_granularity.unwrap_or(4096)).unwrap();
+        } else if params.action == "find buffer headers" {
+            let profiles = buffer_header_profiles(&params);
+            let mut source = buffer_source(&params.data_files[0]);
+            let regions = memory_regions(&params, source.as_mut());
+            let fname = params.data_files.get(2).cloned().unwrap_or_else(|| format!("{}/buffer_headers.txt", params.workdir));
+            buffer_organizer::find_buffer_headers(source.as_mut(), &regions, &profiles, &fname, &params.workdir, params.skip_granularity.unwrap_or(4096));
+        } else if params.action == "dump buffers" {
+            let file_addresses = params.data_files[0].clone();
+            let obj: u32 = params.data_files[1].parse().unwrap();
+            let profiles = buffer_header_profiles(&params);
+            let mut source = buffer_source(&params.data_files[2]);
+            buffer_organizer::dump_buffers(file_addresses, obj, source.as_mut(), &profiles, &params.workdir);
         } else if params.action == "visualize buffers" {
             let file_addresses = params.data_files[0].clone();
             let obj: u32 = params.data_files[1].parse().unwrap();
-            let pid: u32 = params.data_files[2].parse().unwrap();
-            buffer_organizer::visualize_buffers(file_addresses, obj, pid); 
+            let profiles = buffer_header_profiles(&params);
+            let mut source = buffer_source(&params.data_files[2]);
+            buffer_organizer::visualize_buffers(file_addresses, obj, source.as_mut(), &profiles);
         }
     }  else {
-        println!("{:?}", oracle_decoder::guess_type_str(args.manual_string));
+        let options = DecodeOptions { charset: Charset::from_name(&args.charset).unwrap(), ncharset: Charset::from_name(&args.ncharset).unwrap(), ..DecodeOptions::default() };
+        println!("{:?}", oracle_decoder::guess_type_str(args.manual_string, &args.data_type, &options));
     }
 
 }
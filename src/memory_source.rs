@@ -0,0 +1,554 @@
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use proc_maps::{get_process_maps, Pid};
+use crate::block_organizer;
+use crate::block_organizer::BLOCK_SIZE;
+use crate::core_file::CoreFile;
+use crate::snapshot::SnapshotFile;
+
+//how many blocks are read from every candidate map to score it
+const SCORE_SAMPLES: u64 = 256;
+//a region holds the SGA when at least MIN_SCORE of the sampled blocks, and 1 in MIN_SCORE_RATIO, look like Oracle blocks -
+//two signature bytes match in random data now and then
+const MIN_SCORE: u64 = 4;
+const MIN_SCORE_RATIO: u64 = 50;
+//kcbh block types found in the buffer cache: data, index, LOB, undo header, undo, segment header, bitmap blocks
+const BLOCK_TYPES: [u8; 9] = [0x06, 0x28, 0x0e, 0x02, 0x10, 0x20, 0x21, 0x1e, 0x23];
+//kcbh format of 8K blocks
+const BLOCK_FORMAT: u8 = 0xa2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionKind {
+    SysV,
+    DevShm,
+    HugePages,
+    Other,
+}
+
+/// A shared mapping of an Oracle process that can hold a part of the SGA
+#[derive(Debug, Clone)]
+pub struct MemoryRegion {
+    pub start: u64,
+    pub size: u64,
+    pub perms: String,
+    pub name: String,
+    pub kind: RegionKind,
+    /// Sampled blocks with an Oracle block signature
+    pub score: u64,
+}
+
+pub fn region_kind(name: &str) -> Option<RegionKind> {
+    if name.starts_with("/SYSV") {
+        Some(RegionKind::SysV)
+    } else if name.starts_with("/dev/shm/ora_") {
+        Some(RegionKind::DevShm)
+    } else if name.starts_with("/anon_hugepage") || name.contains("hugepages") {
+        Some(RegionKind::HugePages)
+    } else {
+        None
+    }
+}
+
+pub fn looks_like_block(block_data: &[u8]) -> bool {
+    block_data.len() >= 24 && block_data[1] == BLOCK_FORMAT && BLOCK_TYPES.contains(&block_data[0])
+}
+
+/// Where memory is read from: a live process, SysV shared memory segments or /dev/shm granule files.
+/// Addresses are those of regions() - process addresses or addresses of the segments attached to rico3.
+pub trait MemorySource {
+    /// Regions that can hold the SGA
+    fn regions(&self) -> Vec<MemoryRegion>;
+    fn read_at(&mut self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize>;
+    /// Another reader of the same memory, for reading disjoint ranges in parallel
+    fn reader(&self) -> Option<Box<dyn MemorySource + Send>> {
+        None
+    }
+    /// Memory is read at the virtual addresses of the Oracle process, the ones buffer headers point to
+    fn process_addresses(&self) -> bool {
+        true
+    }
+}
+
+/// Readable shared mappings backed by SysV shared memory, /dev/shm/ora_* granule files or hugepages
+pub fn candidate_regions(pid: u32) -> Vec<MemoryRegion> {
+    let maps = get_process_maps(pid as Pid).unwrap();
+    let mut regions: Vec<MemoryRegion> = Vec::new();
+    for map in maps {
+        let name = map.filename().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+        let shared = map.flags.len() > 3 && &map.flags[3..4] == "s";
+        if let Some(kind) = region_kind(&name) {
+            if map.is_read() && shared {
+                regions.push(MemoryRegion { start: map.start() as u64, size: map.size() as u64, perms: map.flags.clone(), name, kind, score: 0 });
+            }
+        }
+    }
+    regions
+}
+
+/// /proc/<pid>/mem of an Oracle process - needs ptrace rights
+pub struct ProcessMemory {
+    pub pid: u32,
+    f: File,
+}
+
+impl ProcessMemory {
+    pub fn open(pid: u32) -> ProcessMemory {
+        let f = File::open(format!("/proc/{}/mem", pid)).unwrap_or_else(|_| panic!("Can't open memory of pid {}", pid));
+        ProcessMemory { pid, f }
+    }
+}
+
+impl MemorySource for ProcessMemory {
+    fn regions(&self) -> Vec<MemoryRegion> {
+        candidate_regions(self.pid)
+    }
+
+    fn read_at(&mut self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.f.seek(SeekFrom::Start(addr))?;
+        self.f.read(buf)
+    }
+
+    fn reader(&self) -> Option<Box<dyn MemorySource + Send>> {
+        Some(Box::new(ProcessMemory::open(self.pid)))
+    }
+}
+
+const PAGE_SIZE: u64 = 4096;
+const IOV_MAX: usize = 1024;
+
+/// process_vm_readv of an Oracle process - no seek and read per chunk, up to IOV_MAX pages per call.
+/// Remote iovecs are single pages, so a read stops exactly at the first page that can't be read.
+pub struct ProcessVm {
+    pub pid: u32,
+}
+
+impl MemorySource for ProcessVm {
+    fn regions(&self) -> Vec<MemoryRegion> {
+        candidate_regions(self.pid)
+    }
+
+    fn read_at(&mut self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut remote: Vec<libc::iovec> = Vec::with_capacity(IOV_MAX);
+        let mut covered: usize = 0;
+        while covered < buf.len() && remote.len() < IOV_MAX {
+            let page_addr = addr + covered as u64;
+            let len = ((PAGE_SIZE - page_addr % PAGE_SIZE) as usize).min(buf.len() - covered);
+            remote.push(libc::iovec { iov_base: page_addr as *mut libc::c_void, iov_len: len });
+            covered += len;
+        }
+        let local = libc::iovec { iov_base: buf.as_mut_ptr() as *mut libc::c_void, iov_len: covered };
+
+        let n = unsafe { libc::process_vm_readv(self.pid as libc::pid_t, &local, 1, remote.as_ptr(), remote.len() as libc::c_ulong, 0) };
+        if n < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    fn reader(&self) -> Option<Box<dyn MemorySource + Send>> {
+        Some(Box::new(ProcessVm { pid: self.pid }))
+    }
+}
+
+struct ShmSegment {
+    shmid: i32,
+    addr: u64,
+    size: u64,
+}
+
+/// SysV shared memory segments from /proc/sysvipc/shm attached read-only - no Oracle process is touched
+pub struct SysvShm {
+    segments: Vec<ShmSegment>,
+}
+
+impl SysvShm {
+    /// All segments, or the ones listed by shmid
+    pub fn attach(shmids: &[i32]) -> SysvShm {
+        let mut segments: Vec<ShmSegment> = Vec::new();
+        let shm_list = fs::read_to_string("/proc/sysvipc/shm").unwrap();
+        for line in shm_list.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 {
+                continue;
+            }
+            let shmid: i32 = fields[1].parse().unwrap();
+            let size: u64 = fields[3].parse().unwrap();
+            if !shmids.is_empty() && !shmids.contains(&shmid) {
+                continue;
+            }
+
+            let addr = unsafe { libc::shmat(shmid, std::ptr::null(), libc::SHM_RDONLY) };
+            if addr as isize == -1 {
+                println!("Can't attach shared memory segment {}: {}", shmid, std::io::Error::last_os_error());
+                continue;
+            }
+            println!("Attached shared memory segment {} of size {} at {:#x}", shmid, size, addr as u64);
+            segments.push(ShmSegment { shmid, addr: addr as u64, size });
+        }
+        SysvShm { segments }
+    }
+}
+
+impl MemorySource for SysvShm {
+    fn regions(&self) -> Vec<MemoryRegion> {
+        self.segments.iter().map(|s| MemoryRegion {
+            start: s.addr,
+            size: s.size,
+            perms: "r--s".to_string(),
+            name: format!("/SYSV shmid {}", s.shmid),
+            kind: RegionKind::SysV,
+            score: 0,
+        }).collect()
+    }
+
+    fn read_at(&mut self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let segment = self.segments.iter().find(|s| addr >= s.addr && addr < s.addr + s.size)
+            .ok_or(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Address out of shared memory segments"))?;
+        let len = buf.len().min((segment.addr + segment.size - addr) as usize);
+        unsafe { std::ptr::copy_nonoverlapping(addr as *const u8, buf.as_mut_ptr(), len) };
+        Ok(len)
+    }
+
+    //segments are attached wherever rico3 gets them
+    fn process_addresses(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for SysvShm {
+    fn drop(&mut self) {
+        for s in &self.segments {
+            unsafe { libc::shmdt(s.addr as *const libc::c_void) };
+        }
+    }
+}
+
+struct GranuleFile {
+    path: String,
+    f: File,
+    start: u64,
+    size: u64,
+}
+
+/// AMM granule files (/dev/shm/ora_<SID>_*), laid out one after another from address 0
+pub struct DevShmFiles {
+    files: Vec<GranuleFile>,
+}
+
+impl DevShmFiles {
+    pub fn open(prefix: &str) -> DevShmFiles {
+        let mut paths: Vec<String> = Vec::new();
+        for entry in fs::read_dir("/dev/shm").unwrap().flatten() {
+            let fname = entry.file_name().to_string_lossy().to_string();
+            if fname.starts_with(prefix) {
+                paths.push(format!("/dev/shm/{}", fname));
+            }
+        }
+        paths.sort();
+
+        let mut files: Vec<GranuleFile> = Vec::new();
+        let mut start = 0;
+        for path in paths {
+            let f = match File::open(&path) {
+                Ok(f) => f,
+                Err(e) => {
+                    println!("Can't open {}: {}", path, e);
+                    continue;
+                }
+            };
+            let size = f.metadata().unwrap().len();
+            files.push(GranuleFile { path, f, start, size });
+            start += size;
+        }
+        DevShmFiles { files }
+    }
+}
+
+impl MemorySource for DevShmFiles {
+    fn regions(&self) -> Vec<MemoryRegion> {
+        self.files.iter().filter(|g| g.size > 0).map(|g| MemoryRegion {
+            start: g.start,
+            size: g.size,
+            perms: "r--s".to_string(),
+            name: g.path.clone(),
+            kind: RegionKind::DevShm,
+            score: 0,
+        }).collect()
+    }
+
+    fn read_at(&mut self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let granule = self.files.iter_mut().find(|g| addr >= g.start && addr < g.start + g.size)
+            .ok_or(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Address out of granule files"))?;
+        let len = buf.len().min((granule.start + granule.size - addr) as usize);
+        granule.f.seek(SeekFrom::Start(addr - granule.start))?;
+        granule.f.read(&mut buf[..len])
+    }
+
+    //granule files are laid out one after another from 0
+    fn process_addresses(&self) -> bool {
+        false
+    }
+}
+
+/// Result of reading a region: bytes read and ranges (start, end) not read - unreadable pages up to the next block boundary
+#[derive(Debug, Default)]
+pub struct ReadStats {
+    pub bytes_read: u64,
+    pub skipped: Vec<(u64, u64)>,
+}
+
+const READ_CHUNK: usize = 1_048_576;
+
+/// Reads a region in chunks of whole blocks. Short reads are continued where they ended, pages that can't be read
+/// are skipped with skip_granularity and the scan goes on from the next block boundary.
+/// on_chunk gets the address and the bytes of every chunk, always whole blocks counted from the start of the region.
+pub fn read_region(source: &mut dyn MemorySource, region: &MemoryRegion, skip_granularity: u64, mut on_chunk: impl FnMut(u64, Vec<u8>)) -> ReadStats {
+    let end = region.start + region.size;
+    let skip_granularity = skip_granularity.max(1);
+    let mut buffer = vec![0_u8; READ_CHUNK];
+    let mut pending: Vec<u8> = Vec::new();
+    let mut pending_start = region.start;
+    let mut pos = region.start;
+    let mut stats = ReadStats::default();
+
+    while pos < end {
+        let want = READ_CHUNK.min((end - pos) as usize);
+        match source.read_at(pos, &mut buffer[..want]) {
+            Ok(n) if n > 0 => {
+                if pending.is_empty() {
+                    pending_start = pos;
+                }
+                pending.extend_from_slice(&buffer[..n]);
+                pos += n as u64;
+                stats.bytes_read += n as u64;
+
+                let whole_blocks = pending.len() / BLOCK_SIZE as usize * BLOCK_SIZE as usize;
+                if whole_blocks > 0 {
+                    let rest = pending.split_off(whole_blocks);
+                    on_chunk(pending_start, std::mem::replace(&mut pending, rest));
+                    pending_start += whole_blocks as u64;
+                }
+            }
+            _ => {
+                //the rest of a block with an unreadable page is of no use, reading goes on at the next block -
+                //blocks are counted from the start of the region, which needn't be block aligned
+                let page_end = (pos / skip_granularity + 1) * skip_granularity;
+                let skip_to = (region.start + (page_end - region.start).div_ceil(BLOCK_SIZE) * BLOCK_SIZE).min(end);
+                match stats.skipped.last_mut() {
+                    Some(last) if last.1 == pos => last.1 = skip_to,
+                    _ => stats.skipped.push((pos, skip_to)),
+                }
+                pending.clear();
+                pos = skip_to;
+            }
+        }
+    }
+    stats
+}
+
+/// Reads regions with several threads, each with its own reader of the source and its own disjoint ranges.
+/// Sources that can't be shared between threads are read by one reader.
+pub fn read_regions_parallel(source: &mut dyn MemorySource, regions: &[MemoryRegion], readers: u8, skip_granularity: u64, on_chunk: impl Fn(u64, Vec<u8>) + Sync) -> ReadStats {
+    let mut stats = ReadStats::default();
+    if readers <= 1 || source.reader().is_none() {
+        for region in regions {
+            let region_stats = read_region(source, region, skip_granularity, &on_chunk);
+            stats.bytes_read += region_stats.bytes_read;
+            stats.skipped.extend(region_stats.skipped);
+        }
+        return stats;
+    }
+
+    let ranges: Vec<MemoryRegion> = regions.iter().flat_map(|r| split_region(r, readers as u64)).collect();
+    let results: Vec<ReadStats> = std::thread::scope(|scope| {
+        let mut handles = Vec::new();
+        for r in 0..readers as usize {
+            let mut reader = source.reader().unwrap();
+            let my_ranges: Vec<&MemoryRegion> = ranges.iter().skip(r).step_by(readers as usize).collect();
+            let on_chunk = &on_chunk;
+            handles.push(scope.spawn(move || {
+                let mut reader_stats = ReadStats::default();
+                for range in my_ranges {
+                    let range_stats = read_region(reader.as_mut(), range, skip_granularity, on_chunk);
+                    reader_stats.bytes_read += range_stats.bytes_read;
+                    reader_stats.skipped.extend(range_stats.skipped);
+                }
+                reader_stats
+            }));
+        }
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    for r in results {
+        stats.bytes_read += r.bytes_read;
+        stats.skipped.extend(r.skipped);
+    }
+    stats.skipped.sort();
+    stats
+}
+
+/// Reads the regions with /proc/<pid>/mem, process_vm_readv and process_vm_readv with parallel readers and reports the speed
+pub fn benchmark(pid: u32, regions: &[MemoryRegion], readers: u8, skip_granularity: u64) {
+    let total: u64 = regions.iter().map(|r| r.size).sum();
+    println!("Benchmarking reads of {} bytes in {} regions of pid {}", total, regions.len(), pid);
+
+    let backends: Vec<(String, Box<dyn MemorySource>, u8)> = vec![
+        ("/proc/pid/mem".to_string(), Box::new(ProcessMemory::open(pid)), 1),
+        ("process_vm_readv".to_string(), Box::new(ProcessVm { pid }), 1),
+        (format!("process_vm_readv x {}", readers), Box::new(ProcessVm { pid }), readers),
+    ];
+    for (name, mut source, backend_readers) in backends {
+        let started = std::time::Instant::now();
+        let stats = read_regions_parallel(source.as_mut(), regions, backend_readers, skip_granularity, |_, _| {});
+        let seconds = started.elapsed().as_secs_f64();
+        println!("{:<28} {:>14} bytes in {:>8.3}s - {:>10.1} MB/s, {} ranges skipped", name, stats.bytes_read, seconds, stats.bytes_read as f64 / 1_048_576.0 / seconds, stats.skipped.len());
+    }
+}
+
+/// Splits a region into parts of whole read chunks, for parallel readers
+pub fn split_region(region: &MemoryRegion, parts: u64) -> Vec<MemoryRegion> {
+    let part_size = (region.size / parts.max(1)).div_ceil(READ_CHUNK as u64).max(1) * READ_CHUNK as u64;
+    let mut split: Vec<MemoryRegion> = Vec::new();
+    let mut start = region.start;
+    while start < region.start + region.size {
+        let size = part_size.min(region.start + region.size - start);
+        split.push(MemoryRegion { start, size, ..region.clone() });
+        start += size;
+    }
+    split
+}
+
+/// shm and devshm sources aren't read at the addresses of the Oracle process
+pub fn spec_process_addresses(spec: &str) -> bool {
+    !(spec == "shm" || spec.starts_with("shm:") || spec == "devshm" || spec.starts_with("devshm:"))
+}
+
+/// pid of a process source spec: <pid> or vm:<pid>
+pub fn spec_pid(spec: &str) -> Option<u32> {
+    spec.strip_prefix("vm:").unwrap_or(spec).parse().ok()
+}
+
+/// Memory source spec: <pid> (/proc/<pid>/mem), vm:<pid> (process_vm_readv), shm (all segments), shm:<shmid>,<shmid>...,
+/// devshm (ora_*), devshm:<file prefix>, core:<ELF core file> or snapshot:<snapshot file>
+pub fn open_source(spec: &str) -> Box<dyn MemorySource> {
+    if let Ok(pid) = spec.parse::<u32>() {
+        Box::new(ProcessMemory::open(pid))
+    } else if let Some(pid) = spec.strip_prefix("vm:") {
+        Box::new(ProcessVm { pid: pid.parse().expect("Wrong pid") })
+    } else if spec == "shm" {
+        Box::new(SysvShm::attach(&[]))
+    } else if let Some(ids) = spec.strip_prefix("shm:") {
+        let shmids: Vec<i32> = ids.split(',').map(|id| id.trim().parse().expect("Wrong shmid")).collect();
+        Box::new(SysvShm::attach(&shmids))
+    } else if spec == "devshm" {
+        Box::new(DevShmFiles::open("ora_"))
+    } else if let Some(prefix) = spec.strip_prefix("devshm:") {
+        let prefix = Path::new(prefix).file_name().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        Box::new(DevShmFiles::open(&prefix))
+    } else if let Some(path) = spec.strip_prefix("core:") {
+        Box::new(CoreFile::open(path).unwrap())
+    } else if let Some(path) = spec.strip_prefix("snapshot:") {
+        Box::new(SnapshotFile::open(path).unwrap())
+    } else {
+        panic!("Unknown memory source {}", spec);
+    }
+}
+
+fn score_step(blocks: u64) -> u64 {
+    (blocks / SCORE_SAMPLES).max(1)
+}
+
+/// Reads blocks spread evenly over the region and counts the ones that look like Oracle blocks
+pub fn score_region(source: &mut dyn MemorySource, region: &MemoryRegion) -> u64 {
+    let blocks = region.size / BLOCK_SIZE;
+    if blocks == 0 {
+        return 0;
+    }
+    let step = score_step(blocks);
+    let mut block_data = [0_u8; BLOCK_SIZE as usize];
+    let mut score = 0;
+    let mut b = 0;
+    while b < blocks {
+        let offset = region.start + b * BLOCK_SIZE;
+        if let Ok(n) = source.read_at(offset, &mut block_data) {
+            if n == block_data.len() && looks_like_block(&block_data) {
+                score += 1;
+            }
+        }
+        b += step;
+    }
+    score
+}
+
+/// Finds the SGA: every region of the source where enough of the sampled blocks are Oracle blocks, ordered by address
+pub fn discover_sga(source: &mut dyn MemorySource, workdir: &str) -> Vec<MemoryRegion> {
+    let mut selected: Vec<MemoryRegion> = Vec::new();
+    for mut region in source.regions() {
+        region.score = score_region(source, &region);
+        let blocks = region.size / BLOCK_SIZE;
+        let samples = blocks.div_ceil(score_step(blocks));
+        let is_sga = region.score >= MIN_SCORE && region.score * MIN_SCORE_RATIO >= samples;
+        let verdict = if is_sga { "selected" } else { "skipped, too few Oracle blocks sampled" };
+        let report = format!("{:?} map {} at {:#x} size {} ({}): score {} of {} samples - {}", region.kind, region.name, region.start, region.size, region.perms, region.score, samples, verdict);
+        println!("{}", report);
+        block_organizer::write_log(workdir.to_string(), report);
+        if is_sga {
+            selected.push(region);
+        }
+    }
+    selected.sort_by_key(|r| r.start);
+    selected
+}
+
+/// The old way: the first map of a process of exactly memory_size bytes
+pub fn region_by_size(pid: u32, memory_size: u64) -> Option<MemoryRegion> {
+    let maps = get_process_maps(pid as Pid).unwrap();
+    let map = maps.iter().find(|m| m.size() as u64 == memory_size)?;
+    let name = map.filename().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+    Some(MemoryRegion {
+        start: map.start() as u64,
+        size: map.size() as u64,
+        perms: map.flags.clone(),
+        kind: region_kind(&name).unwrap_or(RegionKind::Other),
+        name,
+        score: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //memory from 0x1000 to 0x9000 with an unreadable page at 0x3000
+    struct HoleSource;
+
+    impl MemorySource for HoleSource {
+        fn regions(&self) -> Vec<MemoryRegion> {
+            Vec::new()
+        }
+
+        fn read_at(&mut self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+            let readable_to = if addr < 0x3000 { 0x3000 } else if addr < 0x4000 { addr } else { 0x9000 };
+            let len = buf.len().min((readable_to - addr) as usize);
+            if len == 0 {
+                return Err(std::io::Error::other("unreadable"));
+            }
+            buf[..len].fill(1);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn skip_keeps_region_blocks() {
+        let region = MemoryRegion { start: 0x1000, size: 0x8000, perms: "r--s".to_string(), name: "test".to_string(), kind: RegionKind::Other, score: 0 };
+        let mut chunks: Vec<(u64, usize)> = Vec::new();
+        let stats = read_region(&mut HoleSource, &region, 4096, |addr, chunk_bytes| chunks.push((addr, chunk_bytes.len())));
+        assert_eq!(stats.skipped, vec![(0x3000, 0x5000)]);
+        assert_eq!(chunks, vec![(0x1000, 0x2000), (0x5000, 0x4000)]);
+    }
+}
@@ -0,0 +1,252 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::collections::HashMap;
+use crate::oracle_decoder;
+use crate::oracle_decoder::{DecodeOptions, OracleType};
+use crate::oson_decoder::json_string;
+use crate::dictionary::TypeDef;
+
+const PICKLE_FLAG_NO_PREFIX_SEG: u8 = 0x04;
+const PICKLE_LONG_LENGTH: u8 = 0xfe;
+const PICKLE_NULL_LENGTH: u8 = 0xff;
+const PICKLE_ATOMIC_NULL: u8 = 0xfd;
+
+/// Pickled image of a collection or an object (as sent by the server to clients):
+/// flags(1) version(1) length, optional prefix segment, then the data.
+/// Lengths are one byte, or 0xFE followed by four bytes, 0xFF is NULL.
+struct PickleReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PickleReader<'a> {
+    fn raw(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.data.len() {
+            return Err("Pickled image too short".to_string());
+        }
+        let bytes_val = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes_val)
+    }
+
+    fn ub1(&mut self) -> Result<u8, String> {
+        Ok(self.raw(1)?[0])
+    }
+
+    fn peek(&self) -> Result<u8, String> {
+        self.data.get(self.pos).copied().ok_or("Pickled image too short".to_string())
+    }
+
+    fn length(&mut self) -> Result<Option<usize>, String> {
+        match self.ub1()? {
+            PICKLE_NULL_LENGTH => Ok(None),
+            PICKLE_LONG_LENGTH => Ok(Some(u32::from_be_bytes(self.raw(4)?.try_into().unwrap()) as usize)),
+            len => Ok(Some(len as usize)),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<Option<&'a [u8]>, String> {
+        match self.length()? {
+            Some(len) => Ok(Some(self.raw(len)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn header(&mut self) -> Result<(), String> {
+        let flags = self.ub1()?;
+        let _version = self.ub1()?;
+        self.length()?;
+        if flags & PICKLE_FLAG_NO_PREFIX_SEG == 0 {
+            let prefix_len = self.length()?.unwrap_or(0);
+            self.raw(prefix_len)?;
+        }
+        Ok(())
+    }
+}
+
+/// Scalars as JSON: numbers as they are, everything else as strings
+fn scalar_json(bytes_val: Vec<u8>, data_type: &str, options: &DecodeOptions) -> String {
+    let decoded: OracleType = oracle_decoder::decode_type(bytes_val, data_type, options);
+    match decoded.data_type.as_str() {
+        "NULL" => "null".to_string(),
+        "NUMBER" | "FLOAT" | "BINARY_FLOAT" | "BINARY_DOUBLE" if !decoded.value.ends_with("Infinity") && decoded.value != "NaN" => decoded.value,
+        _ => json_string(&decoded.value),
+    }
+}
+
+fn pickled_value(reader: &mut PickleReader, data_type: &str, in_collection: bool, options: &DecodeOptions) -> Result<String, String> {
+    match options.dictionary.type_def(data_type) {
+        Some(type_def) if type_def.is_object() => {
+            let b = reader.peek()?;
+            if b == PICKLE_ATOMIC_NULL || b == PICKLE_NULL_LENGTH {
+                reader.ub1()?;
+                return Ok("null".to_string());
+            }
+            //objects in collections are pickled on their own, attributes of objects are not
+            if in_collection {
+                match reader.bytes()? {
+                    Some(image) => unpickle_object(image, type_def, options),
+                    None => Ok("null".to_string()),
+                }
+            } else {
+                pickled_attributes(reader, type_def, options)
+            }
+        }
+        Some(type_def) if type_def.is_varray() || type_def.is_nested_table() => match reader.bytes()? {
+            Some(image) => unpickle_collection(image, type_def, options),
+            None => Ok("null".to_string()),
+        },
+        _ => match reader.bytes()? {
+            Some(bytes_val) => Ok(scalar_json(bytes_val.to_vec(), data_type, options)),
+            None => Ok("null".to_string()),
+        },
+    }
+}
+
+fn pickled_attributes(reader: &mut PickleReader, type_def: &TypeDef, options: &DecodeOptions) -> Result<String, String> {
+    let mut attributes: Vec<String> = Vec::new();
+    for a in &type_def.attributes {
+        let value = pickled_value(reader, &a.data_type, false, options)?;
+        attributes.push(format!("{}:{}", json_string(&a.name), value));
+    }
+    Ok(format!("{{{}}}", attributes.join(",")))
+}
+
+fn unpickle_object(image: &[u8], type_def: &TypeDef, options: &DecodeOptions) -> Result<String, String> {
+    let mut reader = PickleReader { data: image, pos: 0 };
+    reader.header()?;
+    pickled_attributes(&mut reader, type_def, options)
+}
+
+/// Collection image: header, collection flags(1), number of elements, elements
+pub fn unpickle_collection(image: &[u8], type_def: &TypeDef, options: &DecodeOptions) -> Result<String, String> {
+    let element_type = type_def.element_type.clone().ok_or("Collection type without an element type".to_string())?;
+    let mut reader = PickleReader { data: image, pos: 0 };
+    reader.header()?;
+    let _collection_flags = reader.ub1()?;
+    let count = reader.length()?.unwrap_or(0);
+
+    let mut elements: Vec<String> = Vec::new();
+    for _ in 0..count {
+        elements.push(pickled_value(&mut reader, &element_type, true, options)?);
+    }
+    Ok(format!("[{}]", elements.join(",")))
+}
+
+pub fn check_varray(bytes_val: Vec<u8>, type_def: &TypeDef, options: &DecodeOptions) -> Result<OracleType, String> {
+    Ok(OracleType{data_type: "VARRAY".to_string(), value: unpickle_collection(&bytes_val, type_def, options)?})
+}
+
+/// Nested table columns store the set ID of their rows, which is the NESTED_TABLE_ID column of the storage table
+pub fn nested_table_ref(bytes_val: &[u8], storage_table: Option<u32>) -> String {
+    match storage_table {
+        Some(objd) => format!("NT:{}:{}", objd, hex::encode(bytes_val)),
+        None => format!("NT:{}", hex::encode(bytes_val)),
+    }
+}
+
+fn queue_nested_table_line(workdir: &str, line: String) {
+    let mut f_obj = File::options().append(true).create(true).open(format!("{}/nested_tables.todo", workdir)).unwrap();
+    f_obj.write_all(line.as_bytes()).unwrap();
+}
+
+/// Set IDs are queued in nested_tables.todo during extraction: P|objd|rdba|slot|column|storage objd|set id for
+/// nested table columns, C|storage objd|set id|rdba|slot for rows of storage tables
+pub fn queue_nested_table(workdir: &str, parent: (u32, u32, u16), column: &str, storage_objd: u32, set_id: &[u8]) {
+    let (objd, rdba, slot) = parent;
+    queue_nested_table_line(workdir, format!("P|{}|{}|{}|{}|{}|{}\n", objd, rdba, slot, column, storage_objd, hex::encode(set_id)));
+}
+
+pub fn queue_nested_table_row(workdir: &str, storage_objd: u32, set_id: &[u8], rdba: u32, slot: u16) {
+    queue_nested_table_line(workdir, format!("C|{}|{}|{}|{}\n", storage_objd, hex::encode(set_id), rdba, slot));
+}
+
+/// Links parent rows to the storage table rows of their nested tables when the extraction ends. nested_tables.csv gets
+/// objd|rdba|slot|column|storage objd|set id|rdba|slot for every row of a set, the queue is consumed.
+pub fn link_nested_tables(workdir: &str) {
+    let todo_file = format!("{}/nested_tables.todo", workdir);
+    let todo = match fs::read_to_string(&todo_file) {
+        Ok(todo) => todo,
+        Err(_) => return,
+    };
+    fs::remove_file(&todo_file).unwrap();
+
+    let mut parents: Vec<Vec<&str>> = Vec::new();
+    let mut set_rows: HashMap<(&str, &str), Vec<(&str, &str)>> = HashMap::new();
+    for line in todo.lines() {
+        let fields: Vec<&str> = line.split('|').collect();
+        match fields.as_slice() {
+            ["P", parent @ ..] if parent.len() == 6 => parents.push(parent.to_vec()),
+            ["C", storage_objd, set_id, rdba, slot] => set_rows.entry((*storage_objd, *set_id)).or_default().push((*rdba, *slot)),
+            _ => {}
+        }
+    }
+
+    let mut f_links = File::options().append(true).create(true).open(format!("{}/nested_tables.csv", workdir)).unwrap();
+    let (mut linked, mut empty) = (0, 0);
+    for parent in &parents {
+        match set_rows.get(&(parent[4], parent[5])) {
+            Some(rows) => {
+                for (rdba, slot) in rows {
+                    f_links.write_all(format!("{}|{}|{}\n", parent.join("|"), rdba, slot).as_bytes()).unwrap();
+                    linked += 1;
+                }
+            }
+            None => empty += 1,
+        }
+    }
+    println!("Nested tables: {} storage table rows linked to {} parent rows, no rows found for {} sets", linked, parents.len() - empty, empty);
+}
+
+/// Number of segment columns of an object column: the null indicator and the (flattened) attributes
+pub fn object_width(type_def: &TypeDef, options: &DecodeOptions) -> usize {
+    let mut width = 1;
+    for a in &type_def.attributes {
+        width += match options.dictionary.type_def(&a.data_type) {
+            Some(t) if t.is_object() => object_width(t, options),
+            _ => 1,
+        };
+    }
+    width
+}
+
+fn column_json(bytes_val: &Option<Vec<u8>>, column: &crate::dictionary::ColumnDef, options: &DecodeOptions) -> String {
+    let bytes_val = match bytes_val {
+        Some(b) => b.clone(),
+        None => return "null".to_string(),
+    };
+    match options.dictionary.type_def(&column.data_type) {
+        Some(t) if t.is_nested_table() => json_string(&nested_table_ref(&bytes_val, column.storage_table)),
+        Some(t) if t.is_varray() => unpickle_collection(&bytes_val, t, options).unwrap_or_else(|_| json_string(&hex::encode(&bytes_val))),
+        _ => scalar_json(bytes_val, &column.data_type, options),
+    }
+}
+
+/// Decodes an object column from its segment columns into JSON, returns the number of columns used.
+/// A NULL indicator column means an atomically NULL object.
+pub fn decode_object(row_columns: &[Option<Vec<u8>>], type_def: &TypeDef, options: &DecodeOptions) -> (String, usize) {
+    let width = object_width(type_def, options);
+    if row_columns.first().map(|c| c.is_none()).unwrap_or(true) {
+        return ("null".to_string(), width);
+    }
+
+    let mut attributes: Vec<String> = Vec::new();
+    let mut position = 1;
+    for a in &type_def.attributes {
+        let value = match options.dictionary.type_def(&a.data_type) {
+            Some(t) if t.is_object() => {
+                let (value, used) = decode_object(row_columns.get(position..).unwrap_or(&[]), t, options);
+                position += used;
+                value
+            }
+            _ => {
+                let value = column_json(row_columns.get(position).unwrap_or(&None), a, options);
+                position += 1;
+                value
+            }
+        };
+        attributes.push(format!("{}:{}", json_string(&a.name), value));
+    }
+    (format!("{{{}}}", attributes.join(",")), width)
+}
@@ -1,8 +1,13 @@
-use hex;
-use std::str;
-use rust_decimal::prelude::*;
-use rust_decimal_macros::dec;
-use chrono::NaiveDateTime;
+use std::collections::HashMap;
+use chrono::{NaiveDate, NaiveDateTime};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use crate::charset::Charset;
+use crate::dictionary::{ColumnDef, Dictionary};
+use crate::timezone;
+use crate::oson_decoder;
+use crate::lob_locator;
+use crate::object_decoder;
 
 #[derive(Debug)]
 pub struct OracleType {
@@ -10,178 +15,927 @@ pub struct OracleType {
     pub value: String,
 }
 
-fn check_string(bytes_val: Vec<u8>) -> Result<OracleType, String> {
-    let utfstring = str::from_utf8(&bytes_val);
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    pub charset: Charset,
+    pub ncharset: Charset,
+    pub dictionary: Dictionary,
+    pub dbtimezone: String,
+    pub tz_regions: HashMap<u16, String>,
+    pub binary_float_heuristic: bool,
+    pub bc_format: BcFormat,
+}
 
-    if utfstring.is_ok() {
-        let check_printable = utfstring.unwrap().to_string();
-        if check_printable.chars().all(|x| x.is_alphanumeric() || x.is_ascii() || x.is_ascii_graphic()) {
-            return Ok(OracleType{data_type: "VARCHAR2".to_string(), value: check_printable});
-        } else {
-            return Err("Not a string".to_string());
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            charset: Charset::default(),
+            ncharset: Charset::national(),
+            dictionary: Dictionary::default(),
+            dbtimezone: "+00:00".to_string(),
+            tz_regions: timezone::embedded_regions(),
+            binary_float_heuristic: false,
+            bc_format: BcFormat::Minus,
         }
+    }
+}
+
+pub fn check_string(bytes_val: Vec<u8>, charset: &Charset) -> Result<OracleType, String> {
+    match charset.decode(&bytes_val) {
+        Some(s) if is_string(&s, charset) => Ok(OracleType{data_type: "VARCHAR2".to_string(), value: s}),
+        _ => Err("Not a string".to_string()),
+    }
+}
+
+//multibyte character sets keep the UTF-8 rule (letters and anything ASCII). In single-byte character sets
+//any byte decodes to something, so control characters rule a string out
+fn is_string(s: &str, charset: &Charset) -> bool {
+    if charset.is_single_byte() {
+        is_printable(s)
     } else {
-        return Err("Not a string".to_string());return Err("Not a string".to_string());
+        s.chars().all(|x| x.is_alphanumeric() || x.is_ascii() || x.is_ascii_graphic())
     }
 }
 
-fn check_date(bytes_val: Vec<u8>) -> Result<OracleType, String> {
-    if bytes_val.len() != 7 {
+fn is_printable(s: &str) -> bool {
+    s.chars().all(|x| !x.is_control() || x == '\t' || x == '\n' || x == '\r')
+}
+
+pub fn check_nstring(bytes_val: Vec<u8>, ncharset: &Charset) -> Result<OracleType, String> {
+    match ncharset.decode(&bytes_val) {
+        Some(s) if is_printable(&s) => Ok(OracleType{data_type: "NVARCHAR2".to_string(), value: s}),
+        _ => Err("Not a national string".to_string()),
+    }
+}
+
+//AL16UTF16 text in Latin scripts has every other byte set to zero
+fn looks_like_utf16(bytes_val: &[u8]) -> bool {
+    if bytes_val.len() < 2 || !bytes_val.len().is_multiple_of(2) {
+        return false;
+    }
+    let zeros = bytes_val.iter().step_by(2).filter(|b| **b == 0).count();
+    zeros * 2 >= bytes_val.len() / 2
+}
+
+/// How BC dates are rendered: "minus" -4712-01-01 (as SYYYY), "bc" 4712-01-01 BC,
+/// "iso" -4711-01-01 (ISO 8601 astronomical numbering, 1 BC is year 0000)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BcFormat {
+    Minus,
+    Bc,
+    Iso,
+}
+
+impl BcFormat {
+    pub fn from_name(name: &str) -> Result<BcFormat, String> {
+        match name.to_lowercase().as_str() {
+            "minus" => Ok(BcFormat::Minus),
+            "bc" => Ok(BcFormat::Bc),
+            "iso" => Ok(BcFormat::Iso),
+            _ => Err(format!("Unknown BC date format {}", name)),
+        }
+    }
+}
+
+/// DATE (7 bytes) or TIMESTAMP (11 bytes) image. Years are as in Oracle: -1 is 1 BC,
+/// year 0 doesn't exist in Oracle but is accepted as it is used as a placeholder.
+struct OracleDateTime {
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    fraction: Option<u32>,
+}
+
+//Julian calendar before 1582-10-15, Gregorian after
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let leap = if year > 1582 {
+        (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+    } else {
+        let astronomical = if year < 0 { year + 1 } else { year };
+        astronomical.rem_euclid(4) == 0
+    };
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+//AD: century + 100, year + 100; BC: 100 - century, 100 - year
+fn read_oracle_datetime(bytes_val: &[u8]) -> Result<OracleDateTime, String> {
+    if bytes_val.len() != 7 && bytes_val.len() != 11 {
         return Err("Not a date".to_string());
     }
 
-    let century: i16 = bytes_val[0] as i16 - 100;
-    if century <= 0 {
+    let century = bytes_val[0] as i32;
+    let year_of_century = bytes_val[1] as i32;
+    let year = if century >= 100 && year_of_century >= 100 {
+        (century - 100) * 100 + year_of_century - 100
+    } else if century <= 100 && year_of_century <= 100 {
+        -((100 - century) * 100 + 100 - year_of_century)
+    } else {
+        return Err("Not a date".to_string());
+    };
+    if !(-4712..=9999).contains(&year) || (year_of_century - 100).abs() > 99 {
         return Err("Not a date".to_string());
     }
 
-    let year: i16 = bytes_val[1] as i16 - 100;
-    if year < 0 {
+    let month = bytes_val[2] as u32;
+    let day = bytes_val[3] as u32;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err("Not a date".to_string());
+    }
+    //days skipped by the Gregorian reform
+    if year == 1582 && month == 10 && (5..=14).contains(&day) {
         return Err("Not a date".to_string());
     }
 
-    let month: i8 = bytes_val[2] as i8;
-    let day: i8 = bytes_val[3] as i8;
-    let hour: i8 = bytes_val[4] as i8 - 1;
-    let minute: i8 = bytes_val[5] as i8 -1;
-    let second: i8 = bytes_val[6] as i8 - 1;
+    if !(1..=24).contains(&bytes_val[4]) || !(1..=60).contains(&bytes_val[5]) || !(1..=60).contains(&bytes_val[6]) {
+        return Err("Not a date".to_string());
+    }
 
-    if hour < 0 || minute < 0 || second < 0 {
+    let fraction = if bytes_val.len() == 11 { Some(u32::from_be_bytes(bytes_val[7..11].try_into().unwrap())) } else { None };
+    if fraction.unwrap_or(0) > 999_999_999 {
         return Err("Not a date".to_string());
     }
 
-    let date_string = format!("{:02}{:02}-{:02}-{:02} {:02}:{:02}:{:02}", century, year, month, day, hour, minute, second);
-    let dt = NaiveDateTime::parse_from_str(&date_string, "%Y-%m-%d %H:%M:%S");
-    if dt.is_err() {
+    Ok(OracleDateTime {
+        year,
+        month,
+        day,
+        hour: bytes_val[4] as u32 - 1,
+        minute: bytes_val[5] as u32 - 1,
+        second: bytes_val[6] as u32 - 1,
+        fraction,
+    })
+}
+
+fn format_oracle_datetime(dt: &OracleDateTime, bc_format: BcFormat) -> String {
+    let (year, suffix) = match bc_format {
+        _ if dt.year >= 0 => (format!("{:04}", dt.year), ""),
+        BcFormat::Minus => (format!("-{:04}", -dt.year), ""),
+        BcFormat::Bc => (format!("{:04}", -dt.year), " BC"),
+        BcFormat::Iso if dt.year == -1 => ("0000".to_string(), ""),
+        BcFormat::Iso => (format!("-{:04}", -dt.year - 1), ""),
+    };
+
+    let mut value = format!("{}-{:02}-{:02} {:02}:{:02}:{:02}", year, dt.month, dt.day, dt.hour, dt.minute, dt.second);
+    if let Some(fraction) = dt.fraction {
+        value = format!("{}.{:09}", value, fraction);
+    }
+    format!("{}{}", value, suffix)
+}
+
+pub fn check_date(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    check_date_bc(bytes_val, BcFormat::Minus)
+}
+
+pub fn check_date_bc(bytes_val: Vec<u8>, bc_format: BcFormat) -> Result<OracleType, String> {
+    if bytes_val.len() != 7 {
         return Err("Not a date".to_string());
     }
+    let dt = read_oracle_datetime(&bytes_val)?;
+    Ok(OracleType{data_type: "DATE".to_string(), value: format_oracle_datetime(&dt, bc_format)})
+}
 
-    return Ok(OracleType{data_type: "DATE".to_string(), value: date_string});
-    
+pub fn check_timestamp(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    check_timestamp_bc(bytes_val, BcFormat::Minus)
 }
 
-fn check_timestamp(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+pub fn check_timestamp_bc(bytes_val: Vec<u8>, bc_format: BcFormat) -> Result<OracleType, String> {
     if bytes_val.len() != 11 {
         return Err("Not a date".to_string());
     }
+    let dt = read_oracle_datetime(&bytes_val)?;
+    Ok(OracleType{data_type: "TIMESTAMP".to_string(), value: format_oracle_datetime(&dt, bc_format)})
+}
 
-    let century: i16 = bytes_val[0] as i16 - 100;
-    if century <= 0 {
-        return Err("Not a date".to_string());
+//7 byte DATE or 11 byte TIMESTAMP image, BC years in astronomical numbering.
+//Julian dates that don't exist in the proleptic Gregorian calendar (like 1500-02-29) are rejected.
+fn read_timestamp(bytes_val: &[u8]) -> Result<NaiveDateTime, String> {
+    let dt = read_oracle_datetime(bytes_val).map_err(|_| "Not a timestamp".to_string())?;
+    let year = if dt.year < 0 { dt.year + 1 } else { dt.year };
+
+    NaiveDate::from_ymd_opt(year, dt.month, dt.day)
+        .and_then(|d| d.and_hms_nano_opt(dt.hour, dt.minute, dt.second, dt.fraction.unwrap_or(0)))
+        .ok_or("Not a timestamp".to_string())
+}
+
+//13 bytes: UTC timestamp followed by an hour/minute offset (+20/+60) or a region ID (high bit set)
+pub fn check_timestamp_tz(bytes_val: Vec<u8>, options: &DecodeOptions) -> Result<OracleType, String> {
+    if bytes_val.len() != 13 {
+        return Err("Not a timestamp with time zone".to_string());
     }
+    let utc = read_timestamp(&bytes_val[0..11])?;
 
-    let year: i16 = bytes_val[1] as i16 - 100;
-    if year < 0 {
-        return Err("Not a date".to_string());
+    let value = if bytes_val[11] & 0x80 != 0 {
+        let region_id = (((bytes_val[11] & 0x7f) as u16) << 6) | ((bytes_val[12] & 0xfc) >> 2) as u16;
+        match options.tz_regions.get(&region_id) {
+            Some(region) => timezone::format_with_region(utc, region),
+            None => timezone::format_with_region(utc, &format!("region:{}", region_id)),
+        }
+    } else {
+        let hours = bytes_val[11] as i32 - 20;
+        let minutes = bytes_val[12] as i32 - 60;
+        if !(-12..=14).contains(&hours) || !(-59..=59).contains(&minutes) {
+            return Err("Not a timestamp with time zone".to_string());
+        }
+        timezone::format_with_offset(utc, hours * 60 + minutes)?
+    };
+
+    Ok(OracleType{data_type: "TIMESTAMP WITH TIME ZONE".to_string(), value})
+}
+
+//stored as a plain TIMESTAMP normalized to the database time zone
+fn check_timestamp_ltz(bytes_val: Vec<u8>, options: &DecodeOptions) -> Result<OracleType, String> {
+    let local = read_timestamp(&bytes_val)?;
+    let value = timezone::format_local(local, &options.dbtimezone)?;
+    Ok(OracleType{data_type: "TIMESTAMP WITH LOCAL TIME ZONE".to_string(), value})
+}
+
+//INTERVAL YEAR TO MONTH: 4 byte years biased by 0x80000000, 1 byte months biased by 60
+pub fn check_interval_ym(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    if bytes_val.len() != 5 {
+        return Err("Not an interval".to_string());
+    }
+
+    let years = u32::from_be_bytes(bytes_val[0..4].try_into().unwrap()) as i64 - 0x8000_0000;
+    let months = bytes_val[4] as i64 - 60;
+    if years.abs() > 999_999_999 || months.abs() > 11 || (years < 0 && months > 0) || (years > 0 && months < 0) {
+        return Err("Not an interval".to_string());
     }
 
-    let month: i8 = bytes_val[2] as i8;
-    let day: i8 = bytes_val[3] as i8;
-    let hour: i8 = bytes_val[4] as i8 - 1;
-    let minute: i8 = bytes_val[5] as i8 - 1;
-    let second: i8 = bytes_val[6] as i8 - 1;
-    let second_fraction: u32 = u32::from_be_bytes(bytes_val[7..11].try_into().unwrap());
+    let sign = if years < 0 || months < 0 { "-" } else { "" };
+    let value = format!("{}P{}Y{}M", sign, years.abs(), months.abs());
+    Ok(OracleType{data_type: "INTERVAL YEAR TO MONTH".to_string(), value})
+}
 
-    if hour < 0 || minute < 0 || second < 0 {
-        return Err("Not a date".to_string());
+//INTERVAL DAY TO SECOND: 4 byte days and fractional seconds biased by 0x80000000, hours/minutes/seconds biased by 60
+pub fn check_interval_ds(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    if bytes_val.len() != 11 {
+        return Err("Not an interval".to_string());
     }
 
-    let date_string = format!("{:02}{:02}-{:02}-{:02} {:02}:{:02}:{:02}.{:09}", century, year, month, day, hour, minute, second, second_fraction);
-    let dt = NaiveDateTime::parse_from_str(&date_string, "%Y-%m-%d %H:%M:%S%.f");
-    if dt.is_err() {
-        return Err("Not a date".to_string());
+    let days = u32::from_be_bytes(bytes_val[0..4].try_into().unwrap()) as i64 - 0x8000_0000;
+    let hours = bytes_val[4] as i64 - 60;
+    let minutes = bytes_val[5] as i64 - 60;
+    let seconds = bytes_val[6] as i64 - 60;
+    let fraction = u32::from_be_bytes(bytes_val[7..11].try_into().unwrap()) as i64 - 0x8000_0000;
+
+    let parts = [days, hours, minutes, seconds, fraction];
+    if days.abs() > 999_999_999 || hours.abs() > 23 || minutes.abs() > 59 || seconds.abs() > 59 || fraction.abs() > 999_999_999 {
+        return Err("Not an interval".to_string());
+    }
+    if parts.iter().any(|p| *p < 0) && parts.iter().any(|p| *p > 0) {
+        return Err("Not an interval".to_string());
     }
 
-    return Ok(OracleType{data_type: "TIMESTAMP".to_string(), value: date_string});
-    
+    let sign = if parts.iter().any(|p| *p < 0) { "-" } else { "" };
+    let mut value = format!("{}P{}DT{}H{}M{}", sign, days.abs(), hours.abs(), minutes.abs(), seconds.abs());
+    if fraction != 0 {
+        value = format!("{}.{}", value, format!("{:09}", fraction.abs()).trim_end_matches('0'));
+    }
+    value.push('S');
+
+    Ok(OracleType{data_type: "INTERVAL DAY TO SECOND".to_string(), value})
 }
 
+//IEEE bytes with the sign bit flipped for positive values and all bits inverted for negative ones
+fn unflip_binary_float(bytes_val: &[u8]) -> Vec<u8> {
+    let mut ieee = bytes_val.to_vec();
+    if ieee[0] & 0x80 != 0 {
+        ieee[0] &= 0x7f;
+    } else {
+        ieee.iter_mut().for_each(|b| *b = !*b);
+    }
+    ieee
+}
 
-fn check_number(bytes_val: Vec<u8>) -> Result<OracleType, String> {
-    if bytes_val.len() == 1 && bytes_val[0] == 128 {
-        return Ok(OracleType { data_type: "NUMBER".to_string(), value: "0".to_string() });
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity".to_string() } else { "-Infinity".to_string() }
+    } else if value != 0.0 && (value.abs() >= 1e16 || value.abs() < 1e-5) {
+        format!("{:E}", value)
+    } else {
+        format!("{}", value)
     }
+}
 
-    let mut exp = 0;
-    let mut number_value = "0.".to_string();
+fn binary_float_value(bytes_val: &[u8]) -> String {
+    let value = f32::from_be_bytes(unflip_binary_float(bytes_val).try_into().unwrap());
+    //shortest representation of the f32, not of its f64 widening
+    if value.is_finite() { format_float(value.to_string().parse::<f64>().unwrap()) } else { format_float(value as f64) }
+}
 
-    let last_byte_idx = bytes_val.len() - 1;
-    if (bytes_val[last_byte_idx] != 102 && bytes_val[0] < 193) || bytes_val[0] > 208 {
-        return Err("Not a NUMBER".to_string());
-    } else if bytes_val[last_byte_idx] != 102 && bytes_val[0] >= 193 {
-        exp = (bytes_val[0] - 193) * 2 + 2;
-        
-        for i in 1..last_byte_idx+1 {
-            number_value = format!("{}{:02}", number_value, bytes_val[i] - 1);
-        }
-        
-    } else if bytes_val[last_byte_idx] == 102 && bytes_val[0] <= 62 {
-        number_value = "-0.".to_string();
-        exp = (62 - bytes_val[0]) * 2 + 2;
-
-        for i in 1..last_byte_idx+1 {
-            if (101 as i64 - bytes_val[i] as i64) < 0 {
-                return Err("Not a NUMBER".to_string());
+pub fn check_binary_float(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    if bytes_val.len() != 4 {
+        return Err("Not a BINARY_FLOAT".to_string());
+    }
+    Ok(OracleType{data_type: "BINARY_FLOAT".to_string(), value: binary_float_value(&bytes_val)})
+}
+
+pub fn check_binary_double(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    if bytes_val.len() != 8 {
+        return Err("Not a BINARY_DOUBLE".to_string());
+    }
+    let value = f64::from_be_bytes(unflip_binary_float(&bytes_val).try_into().unwrap());
+    Ok(OracleType{data_type: "BINARY_DOUBLE".to_string(), value: format_float(value)})
+}
+
+//guessing only accepts normal numbers and the NaN/Inf values Oracle itself writes
+fn check_binary_float_heuristic(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    let canonical = [
+        vec![0xff, 0xc0, 0x00, 0x00], vec![0xff, 0x80, 0x00, 0x00], vec![0x00, 0x7f, 0xff, 0xff],
+        vec![0xff, 0xf8, 0, 0, 0, 0, 0, 0], vec![0xff, 0xf0, 0, 0, 0, 0, 0, 0], vec![0x00, 0x0f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+    ];
+    if canonical.contains(&bytes_val) {
+        return if bytes_val.len() == 4 { check_binary_float(bytes_val) } else { check_binary_double(bytes_val) };
+    }
+
+    match bytes_val.len() {
+        4 if f32::from_be_bytes(unflip_binary_float(&bytes_val).try_into().unwrap()).is_normal() => check_binary_float(bytes_val),
+        8 if f64::from_be_bytes(unflip_binary_float(&bytes_val).try_into().unwrap()).is_normal() => check_binary_double(bytes_val),
+        _ => Err("Not a BINARY_FLOAT or BINARY_DOUBLE".to_string()),
+    }
+}
+
+fn check_boolean(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    match bytes_val.as_slice() {
+        [1] => Ok(OracleType{data_type: "BOOLEAN".to_string(), value: "TRUE".to_string()}),
+        [0] => Ok(OracleType{data_type: "BOOLEAN".to_string(), value: "FALSE".to_string()}),
+        _ => Err("Not a BOOLEAN".to_string()),
+    }
+}
+
+const VECTOR_MAGIC: u8 = 0xDB;
+const VECTOR_FLAG_NORM: u16 = 0x0002;
+//set instead of VECTOR_FLAG_NORM by some versions, the 8 bytes of the norm are there as well
+const VECTOR_FLAG_NORM_RESERVED: u16 = 0x0010;
+const VECTOR_FLAG_SPARSE: u16 = 0x0020;
+
+//element values of a vector image, stored with the same encoding as BINARY_FLOAT/BINARY_DOUBLE
+fn vector_elements(bytes_val: &[u8], format: u8, count: usize) -> Result<(Vec<String>, usize), String> {
+    let width = match format {
+        2 => 4,
+        3 => 8,
+        4 | 5 => 1,
+        _ => return Err("Unknown VECTOR format".to_string()),
+    };
+    //BINARY vectors pack 8 dimensions per byte
+    let count = if format == 5 { count.div_ceil(8) } else { count };
+    if bytes_val.len() < count * width {
+        return Err("VECTOR image too short".to_string());
+    }
+
+    let elements = bytes_val[..count * width].chunks(width).map(|e| match format {
+        2 => binary_float_value(e),
+        3 => format_float(f64::from_be_bytes(unflip_binary_float(e).try_into().unwrap())),
+        4 => (e[0] as i8).to_string(),
+        _ => e[0].to_string(),
+    }).collect();
+    Ok((elements, count * width))
+}
+
+//magic, version, flags, element format (2 FLOAT32, 3 FLOAT64, 4 INT8, 5 BINARY), dimensions, [norm], elements
+pub fn check_vector(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    if bytes_val.len() < 9 || bytes_val[0] != VECTOR_MAGIC || bytes_val[1] > 2 {
+        return Err("Not a VECTOR".to_string());
+    }
+
+    let flags = u16::from_be_bytes(bytes_val[2..4].try_into().unwrap());
+    let format = bytes_val[4];
+    let dimensions = u32::from_be_bytes(bytes_val[5..9].try_into().unwrap()) as usize;
+    let mut position = 9;
+    if flags & (VECTOR_FLAG_NORM | VECTOR_FLAG_NORM_RESERVED) != 0 {
+        position += 8;
+    }
+    if position > bytes_val.len() {
+        return Err("Not a VECTOR".to_string());
+    }
+
+    let value = if flags & VECTOR_FLAG_SPARSE != 0 {
+        //sparse vectors are rendered like Oracle does: [dimensions,[indices],[values]]
+        if position + 2 > bytes_val.len() {
+            return Err("Not a VECTOR".to_string());
+        }
+        let nonzero = u16::from_be_bytes(bytes_val[position..position + 2].try_into().unwrap()) as usize;
+        position += 2;
+        if position + nonzero * 4 > bytes_val.len() {
+            return Err("Not a VECTOR".to_string());
+        }
+        let indices: Vec<String> = bytes_val[position..position + nonzero * 4].chunks(4)
+            .map(|i| u32::from_be_bytes(i.try_into().unwrap()).to_string()).collect();
+        position += nonzero * 4;
+        let (elements, used) = vector_elements(&bytes_val[position..], format, nonzero)?;
+        position += used;
+        format!("[{},[{}],[{}]]", dimensions, indices.join(","), elements.join(","))
+    } else {
+        let (elements, used) = vector_elements(&bytes_val[position..], format, dimensions)?;
+        position += used;
+        format!("[{}]", elements.join(","))
+    };
+
+    if position != bytes_val.len() {
+        return Err("Not a VECTOR".to_string());
+    }
+    Ok(OracleType{data_type: "VECTOR".to_string(), value})
+}
+
+const ROWID_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+//fixed width base64 number, as used in the ROWID string representation
+fn rowid_base64(value: u64, width: u32) -> String {
+    (0..width).rev().map(|i| ROWID_ALPHABET[((value >> (6 * i)) & 0x3f) as usize] as char).collect()
+}
+
+//extended ROWID: 4 byte data object id, 4 byte dba (10 bit relative file, 22 bit block), 2 byte slot
+fn check_rowid(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    if bytes_val.len() != 10 {
+        return Err("Not a ROWID".to_string());
+    }
+
+    let objd = u32::from_be_bytes(bytes_val[0..4].try_into().unwrap());
+    let dba = u32::from_be_bytes(bytes_val[4..8].try_into().unwrap());
+    let slot = u16::from_be_bytes(bytes_val[8..10].try_into().unwrap());
+    let rfile = dba >> 22;
+    let block = dba & 0x003f_ffff;
+
+    let value = format!("{}{}{}{} objd={} rfile={} block={} slot={}",
+        rowid_base64(objd as u64, 6), rowid_base64(rfile as u64, 3), rowid_base64(block as u64, 6), rowid_base64(slot as u64, 3),
+        objd, rfile, block, slot);
+    Ok(OracleType{data_type: "ROWID".to_string(), value})
+}
+
+//guessing accepts only rowids pointing at a real block
+fn check_rowid_heuristic(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    if bytes_val.len() != 10 || bytes_val[0..4] == [0, 0, 0, 0] || bytes_val[4..8] == [0, 0, 0, 0] {
+        return Err("Not a ROWID".to_string());
+    }
+    check_rowid(bytes_val)
+}
+
+//UROWID: type byte 1 - physical rowid follows, type byte 2 - logical rowid of an IOT (guess dba + primary key)
+fn check_urowid(bytes_val: Vec<u8>, options: &DecodeOptions) -> Result<OracleType, String> {
+    if bytes_val.len() < 2 {
+        return Err("Not a UROWID".to_string());
+    }
+
+    match bytes_val[0] {
+        1 => check_rowid(bytes_val[1..].to_vec()).map(|mut r| {
+            r.data_type = "UROWID".to_string();
+            r
+        }),
+        2 => {
+            let mut value = format!("*{}", STANDARD_NO_PAD.encode(&bytes_val[1..]));
+            if let Some(key) = logical_rowid_key(&bytes_val[1..], options) {
+                value = format!("{} {}", value, key);
             }
-            number_value = format!("{}{:02}", number_value, 101 - bytes_val[i]);
+            Ok(OracleType{data_type: "UROWID".to_string(), value})
         }
+        _ => Err("Not a UROWID".to_string()),
+    }
+}
 
+//length prefixed guess dba followed by length prefixed primary key columns
+fn logical_rowid_key(bytes_val: &[u8], options: &DecodeOptions) -> Option<String> {
+    let guess_len = *bytes_val.first()? as usize;
+    let guess = bytes_val.get(1..1 + guess_len)?;
+    let mut parts = vec![format!("guess={}", hex::encode(guess))];
+
+    let mut position = 1 + guess_len;
+    let mut key: Vec<String> = Vec::new();
+    while position < bytes_val.len() {
+        let col_len = bytes_val[position] as usize;
+        if col_len >= 250 {
+            break;
+        }
+        let col_data = bytes_val.get(position + 1..position + 1 + col_len)?;
+        key.push(guess_type(col_data.to_vec(), options).value);
+        position += 1 + col_len;
+    }
+    parts.push(format!("key=[{}]", key.join(",")));
+    Some(parts.join(" "))
+}
+
+fn format_number(negative: bool, digits: &str, point: i64) -> String {
+    //digits has no leading or trailing zeros and the decimal point sits after `point` digits
+    let sign = if negative { "-" } else { "" };
+    let len = digits.len() as i64;
+
+    let plain = if point <= 0 {
+        format!("{}0.{}{}", sign, "0".repeat((-point) as usize), digits)
+    } else if point >= len {
+        format!("{}{}{}", sign, digits, "0".repeat((point - len) as usize))
     } else {
+        format!("{}{}.{}", sign, &digits[..point as usize], &digits[point as usize..])
+    };
+
+    if plain.len() <= 40 {
+        return plain;
+    }
+
+    let exponent = point - 1;
+    let mantissa = if len > 1 {
+        format!("{}.{}", &digits[..1], &digits[1..])
+    } else {
+        digits.to_string()
+    };
+    let exp_sign = if exponent < 0 { "-" } else { "+" };
+    format!("{}{}E{}{}", sign, mantissa, exp_sign, exponent.abs())
+}
+
+pub fn check_number(bytes_val: Vec<u8>) -> Result<OracleType, String> {
+    if bytes_val.is_empty() || bytes_val.len() > 21 {
         return Err("Not a NUMBER".to_string());
     }
 
-    let number_decimal = Decimal::from_str(&number_value);
-    if number_decimal.is_err() {
+    if bytes_val.len() == 1 && bytes_val[0] == 128 {
+        return Ok(OracleType { data_type: "NUMBER".to_string(), value: "0".to_string() });
+    }
+    if bytes_val.len() == 1 && bytes_val[0] == 0 {
+        return Ok(OracleType { data_type: "NUMBER".to_string(), value: "-Infinity".to_string() });
+    }
+    if bytes_val.len() == 2 && bytes_val[0] == 255 && bytes_val[1] == 101 {
+        return Ok(OracleType { data_type: "NUMBER".to_string(), value: "Infinity".to_string() });
+    }
+
+    let negative = bytes_val[0] < 128;
+    let mut mantissa = &bytes_val[1..];
+    let exp: i64;
+
+    if negative {
+        //negative numbers shorter than 20 mantissa bytes are terminated with 102
+        if mantissa.last() == Some(&102) {
+            mantissa = &mantissa[..mantissa.len() - 1];
+        } else if mantissa.len() < 20 {
+            return Err("Not a NUMBER".to_string());
+        }
+        exp = 62 - bytes_val[0] as i64;
+    } else {
+        exp = bytes_val[0] as i64 - 193;
+    }
+
+    if mantissa.is_empty() {
         return Err("Not a NUMBER".to_string());
     }
 
-    let mut number_decimal = number_decimal.unwrap();
-    for i in 0..exp {
-        let check_overflow =  number_decimal.checked_mul(dec!(10));
-        if check_overflow.is_none() {
+    let mut digits = String::with_capacity(mantissa.len() * 2);
+    for b in mantissa {
+        let d = if negative { 101 - *b as i64 } else { *b as i64 - 1 };
+        if !(0..=99).contains(&d) {
             return Err("Not a NUMBER".to_string());
         }
-        number_decimal = number_decimal * dec!(10);
+        digits.push_str(&format!("{:02}", d));
+    }
+
+    //Oracle never stores leading or trailing zero base-100 digits
+    if digits.starts_with("00") || digits.ends_with("00") {
+        return Err("Not a NUMBER".to_string());
+    }
+
+    let mut point = (exp + 1) * 2;
+    if digits.starts_with('0') {
+        digits.remove(0);
+        point -= 1;
     }
+    let digits = digits.trim_end_matches('0');
 
-    return Ok(OracleType { data_type: "NUMBER".to_string(), value: number_decimal.to_string() });
+    Ok(OracleType { data_type: "NUMBER".to_string(), value: format_number(negative, digits, point) })
+}
 
+//in single-byte character sets accented letters are also NUMBER exponents (AF 55 4B is both "ŻUK" and 8.474E-35).
+//Printable text is taken for a NUMBER only with an exponent of everyday numbers (1E-10 to 1E16) and not when it's all letters
+fn number_over_text(bytes_val: &[u8], text: &str) -> bool {
+    let exp = if bytes_val[0] < 128 { 62 - bytes_val[0] as i64 } else { bytes_val[0] as i64 - 193 };
+    (-5..=7).contains(&exp) && !text.chars().all(|c| c.is_alphabetic() || c == ' ')
 }
 
-pub fn guess_type(byte_intput: Vec<u8>) -> OracleType {
+pub fn guess_type(byte_intput: Vec<u8>, options: &DecodeOptions) -> OracleType {
     //println!("\t\tGuessing {:x?}", byte_intput.as_slice());
-    if byte_intput.len() == 0 {
+    if byte_intput.is_empty() {
         return OracleType{data_type: "Unrecognized".to_string(), value: "NONE".to_string()};
     }
-    if byte_intput[0] == 255 {
+
+    //-Infinity is a single 0x00 byte, which would otherwise pass as VARCHAR2
+    if byte_intput == [0] {
+        if let Ok(result) = check_number(byte_intput.clone()) {
+            return result;
+        }
+    }
+
+    //NUMBERs with the largest exponents (and +Infinity) start with 0xFF as well
+    if byte_intput == [255] {
         return OracleType{data_type: "NULL".to_string(), value: "NULL".to_string()};
     }
 
-    let result = check_date(byte_intput.clone());
-    if result.is_ok() {
-        return result.unwrap();
+    if let Ok(result) = check_date_bc(byte_intput.clone(), options.bc_format) {
+        return result;
+    }
+
+    if let Ok(result) = check_timestamp_bc(byte_intput.clone(), options.bc_format) {
+        return result;
+    }
+
+    if let Ok(result) = check_timestamp_tz(byte_intput.clone(), options) {
+        return result;
+    }
+
+    if lob_locator::is_lob_locator(&byte_intput) {
+        if let Ok(result) = lob_locator::check_lob(byte_intput.clone(), None, options) {
+            return result;
+        }
+    }
+
+    if oson_decoder::is_oson(&byte_intput) {
+        if let Ok(result) = oson_decoder::check_oson(byte_intput.clone(), options) {
+            return result;
+        }
+    }
+
+    if byte_intput[0] == VECTOR_MAGIC {
+        if let Ok(result) = check_vector(byte_intput.clone()) {
+            return result;
+        }
+    }
+
+    if options.ncharset.is_utf16() && looks_like_utf16(&byte_intput) {
+        if let Ok(result) = check_nstring(byte_intput.clone(), &options.ncharset) {
+            return result;
+        }
+    }
+
+    if let Ok(result) = check_string(byte_intput.clone(), &options.charset) {
+        //NUMBERs like C3 2D 2E are valid text in single-byte character sets
+        if options.charset.is_single_byte() && number_over_text(&byte_intput, &result.value) {
+            if let Ok(number) = check_number(byte_intput.clone()) {
+                return number;
+            }
+        }
+        return result;
+    }
+
+    if let Ok(result) = check_interval_ym(byte_intput.clone()) {
+        return result;
+    }
+
+    if let Ok(result) = check_interval_ds(byte_intput.clone()) {
+        return result;
     }
 
-    let result = check_timestamp(byte_intput.clone());
-    if result.is_ok() {
-        return result.unwrap();
+    if let Ok(result) = check_number(byte_intput.clone()) {
+        return result;
     }
-    
-    let result = check_string(byte_intput.clone());
-    if result.is_ok() {
-        return result.unwrap();
-    } 
-    
-    let result = check_number(byte_intput.clone());
-    if result.is_ok() {
-        return result.unwrap();
-    } 
 
-    return OracleType{data_type: "Unrecognized".to_string(), value: "NONE".to_string()};
+    //any 4 or 8 bytes with a normal exponent pass, so strings and NUMBERs go first
+    if options.binary_float_heuristic {
+        if let Ok(result) = check_binary_float_heuristic(byte_intput.clone()) {
+            return result;
+        }
+    }
+
+    if let Ok(result) = check_rowid_heuristic(byte_intput.clone()) {
+        return result;
+    }
+
+    OracleType{data_type: "Unrecognized".to_string(), value: "NONE".to_string()}
 }
 
-pub fn guess_type_str(string_val: String) -> OracleType {
+//"TIMESTAMP(6) WITH TIME ZONE" -> "TIMESTAMP WITH TIME ZONE"
+pub fn base_type(data_type: &str) -> String {
+    let mut base = String::new();
+    let mut depth = 0;
+    for c in data_type.trim().to_uppercase().chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ if depth == 0 => base.push(c),
+            _ => {}
+        }
+    }
+    base.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Decodes a column whose type is known from the dictionary, falls back to guessing
+pub fn decode_type(byte_intput: Vec<u8>, data_type: &str, options: &DecodeOptions) -> OracleType {
+    let data_type = base_type(data_type);
+    let result = match data_type.as_str() {
+        "NUMBER" | "FLOAT" => check_number(byte_intput.clone()),
+        "DATE" => check_date_bc(byte_intput.clone(), options.bc_format),
+        "TIMESTAMP" => check_timestamp_bc(byte_intput.clone(), options.bc_format),
+        "TIMESTAMP WITH TIME ZONE" => check_timestamp_tz(byte_intput.clone(), options),
+        "TIMESTAMP WITH LOCAL TIME ZONE" => check_timestamp_ltz(byte_intput.clone(), options),
+        "INTERVAL YEAR TO MONTH" => check_interval_ym(byte_intput.clone()),
+        "BINARY_FLOAT" => check_binary_float(byte_intput.clone()),
+        "BINARY_DOUBLE" => check_binary_double(byte_intput.clone()),
+        "ROWID" => check_rowid(byte_intput.clone()),
+        "BOOLEAN" => check_boolean(byte_intput.clone()),
+        "CLOB" | "NCLOB" | "BLOB" => lob_locator::check_lob(byte_intput.clone(), Some(&data_type), options),
+        "VECTOR" | "JSON" if lob_locator::is_lob_locator(&byte_intput) => lob_locator::check_lob(byte_intput.clone(), Some(&data_type), options),
+        "VECTOR" => check_vector(byte_intput.clone()),
+        "JSON" => oson_decoder::check_oson(byte_intput.clone(), options),
+        "UROWID" => check_urowid(byte_intput.clone(), options),
+        "INTERVAL DAY TO SECOND" => check_interval_ds(byte_intput.clone()),
+        "VARCHAR2" | "CHAR" | "VARCHAR" | "LONG" => check_string(byte_intput.clone(), &options.charset),
+        "LONG RAW" | "RAW" => Ok(OracleType{data_type: data_type.clone(), value: hex::encode(&byte_intput)}),
+        "NVARCHAR2" | "NCHAR" => check_nstring(byte_intput.clone(), &options.ncharset),
+        _ => match options.dictionary.type_def(&data_type) {
+            Some(t) if t.is_varray() && lob_locator::is_lob_locator(&byte_intput) => lob_locator::check_lob(byte_intput.clone(), Some("BLOB"), options),
+            Some(t) if t.is_varray() => object_decoder::check_varray(byte_intput.clone(), t, options),
+            Some(t) if t.is_nested_table() => Ok(OracleType{data_type: data_type.clone(), value: object_decoder::nested_table_ref(&byte_intput, None)}),
+            _ => Err(format!("Unsupported data type {}", data_type)),
+        },
+    };
+
+    match result {
+        Ok(mut r) => {
+            r.data_type = data_type;
+            r
+        }
+        Err(_) => guess_type(byte_intput, options),
+    }
+}
+
+/// Decodes a column by its position in the table definition, guesses when the table or column is unknown
+pub fn decode_column(col_data: Vec<u8>, columns: &[ColumnDef], col_idx: usize, options: &DecodeOptions) -> OracleType {
+    match columns.get(col_idx) {
+        Some(c) if options.dictionary.type_def(&c.data_type).map(|t| t.is_nested_table()).unwrap_or(false) => {
+            OracleType{data_type: base_type(&c.data_type), value: object_decoder::nested_table_ref(&col_data, c.storage_table)}
+        }
+        Some(c) => decode_type(col_data, &c.data_type, options),
+        None => guess_type(col_data, options),
+    }
+}
+
+pub fn guess_type_str(string_val: String, data_type: &str, options: &DecodeOptions) -> OracleType {
     let byte_intput = hex::decode(string_val).unwrap();
-    return guess_type(byte_intput.clone());
+    if data_type == "GUESS" {
+        guess_type(byte_intput, options)
+    } else {
+        decode_type(byte_intput, data_type, options)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //2000-01-01 00:00:00 UTC with region ID 340
+    const TSTZ_REGION: [u8; 13] = [120, 100, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0x80 | (340 >> 6) as u8, ((340 & 0x3f) << 2) as u8];
+
+    fn decoded(result: Result<OracleType, String>) -> String {
+        result.map(|r| r.value).unwrap_or_else(|e| format!("Err: {}", e))
+    }
+
+    #[test]
+    fn number_values() {
+        let cases = [
+            ("80", "0"),
+            ("c102", "1"),
+            ("3e6466", "-1"),
+            ("c20b", "1000"),
+            ("c1020b", "1.1"),
+            ("c1021f", "1.3"),
+            ("c0033c", "0.0259"),
+            ("c0b3", "Err: Not a NUMBER"),
+            ("c033", "0.5"),
+            ("c2021846", "123.69"),
+            ("3d644e3866", "-123.45"),
+            ("c402", "1000000"),
+            ("d30d23394f5b0d23394f5b0d23394f5b0d23394f", "12345678901234567890123456789012345678"),
+            ("ff02", "1E+124"),
+            ("ff6464646464646464646464646464646464646464", "9.999999999999999999999999999999999999999E+125"),
+            ("8002", "1E-130"),
+            ("006466", "-1E+124"),
+            ("ff65", "Infinity"),
+            ("00", "-Infinity"),
+            ("c10201", "Err: Not a NUMBER"),
+            ("3e64", "Err: Not a NUMBER"),
+            ("c1", "Err: Not a NUMBER"),
+            ("c165", "Err: Not a NUMBER"),
+        ];
+        for (hex_val, number) in cases {
+            assert_eq!(decoded(check_number(hex::decode(hex_val).unwrap())), number, "{}", hex_val);
+        }
+    }
+
+    #[test]
+    fn number_formatting() {
+        let cases = [
+            ((false, "1", 1), "1"),
+            ((true, "5", 0), "-0.5"),
+            ((false, "1", -2), "0.001"),
+            ((false, "25", 4), "2500"),
+            ((false, "12345", 3), "123.45"),
+            ((false, "1", 41), "1E+40"),
+            ((false, "1", 40), "1000000000000000000000000000000000000000"),
+            ((true, "15", -40), "-1.5E-41"),
+        ];
+        for ((negative, digits, point), number) in cases {
+            assert_eq!(format_number(negative, digits, point), number, "{} {}", digits, point);
+        }
+    }
+
+    #[test]
+    fn date_values() {
+        let cases = [
+            ("787c021d0e2e1f", BcFormat::Minus, "2024-02-29 13:45:30"),
+            ("787b021d010101", BcFormat::Minus, "Err: Not a date"),
+            ("73b60a0a010101", BcFormat::Minus, "Err: Not a date"),
+            ("73b60a0f010101", BcFormat::Minus, "1582-10-15 00:00:00"),
+            ("35580101010101", BcFormat::Minus, "-4712-01-01 00:00:00"),
+            ("35580101010101", BcFormat::Bc, "4712-01-01 00:00:00 BC"),
+            ("35580101010101", BcFormat::Iso, "-4711-01-01 00:00:00"),
+            ("6463030c181818", BcFormat::Iso, "0000-03-12 23:23:23"),
+            ("6463021d010101", BcFormat::Bc, "0001-02-29 00:00:00 BC"),
+        ];
+        for (hex_val, bc_format, date) in cases {
+            assert_eq!(decoded(check_date_bc(hex::decode(hex_val).unwrap(), bc_format)), date, "{}", hex_val);
+        }
+        assert_eq!(decoded(check_timestamp_bc(hex::decode("787c021d0e2e1f075bcd15").unwrap(), BcFormat::Minus)), "2024-02-29 13:45:30.123456789");
+        assert_eq!(decoded(check_timestamp_bc(hex::decode("3558010101010100000000").unwrap(), BcFormat::Bc)), "4712-01-01 00:00:00.000000000 BC");
+    }
+
+    #[test]
+    fn interval_values() {
+        let cases = [
+            ("800000013e", "P1Y2M"),
+            ("7fffffff3a", "-P1Y2M"),
+            ("800000003c", "P0Y0M"),
+            ("800000023a", "Err: Not an interval"),
+        ];
+        for (hex_val, interval) in cases {
+            assert_eq!(decoded(check_interval_ym(hex::decode(hex_val).unwrap())), interval, "{}", hex_val);
+        }
+
+        let cases = [
+            ("800000034041429dcd6500", "P3DT4H5M6.5S"),
+            ("800000003c3c3b80000000", "-P0DT0H0M1S"),
+            ("800000003c3c3c80000001", "P0DT0H0M0.000000001S"),
+            ("800000013c3c3b80000000", "Err: Not an interval"),
+        ];
+        for (hex_val, interval) in cases {
+            assert_eq!(decoded(check_interval_ds(hex::decode(hex_val).unwrap())), interval, "{}", hex_val);
+        }
+    }
+
+    #[test]
+    fn rowid_values() {
+        let rowid = "AAASMQAAEAAAACDAAA objd=74512 rfile=4 block=131 slot=0";
+        assert_eq!(decoded(check_rowid(hex::decode("0001231001000083000a").unwrap())), "AAASMQAAEAAAACDAAK objd=74512 rfile=4 block=131 slot=10");
+        assert_eq!(decoded(check_urowid(vec![1], &DecodeOptions::default())), "Err: Not a UROWID");
+        assert_eq!(decoded(check_urowid(hex::decode("0100012310010000830000").unwrap(), &DecodeOptions::default())), rowid);
+        assert_eq!(decoded(check_rowid_heuristic(hex::decode("00000000010000830000").unwrap())), "Err: Not a ROWID");
+        assert_eq!(decoded(check_rowid(hex::decode("000123100100008300").unwrap())), "Err: Not a ROWID");
+    }
+
+    fn guess(hex_val: &str, charset: &str) -> OracleType {
+        let options = DecodeOptions { charset: Charset::from_name(charset).unwrap(), ..Default::default() };
+        guess_type(hex::decode(hex_val).unwrap(), &options)
+    }
+
+    #[test]
+    fn single_byte_words_are_strings() {
+        for (hex_val, charset, text) in [("af554b", "EE8MSWIN1250", "ŻUK"), ("8c61", "EE8MSWIN1250", "Śa"), ("c9434f4c45", "WE8ISO8859P1", "ÉCOLE")] {
+            let result = guess(hex_val, charset);
+            assert_eq!((result.data_type.as_str(), result.value.as_str()), ("VARCHAR2", text), "{}", hex_val);
+        }
+    }
+
+    #[test]
+    fn single_byte_numbers_are_numbers() {
+        for (hex_val, charset, number) in [("c32d2e", "EE8MSWIN1250", "444500"), ("c32d2e", "WE8ISO8859P1", "444500"), ("c2022e", "EE8MSWIN1250", "145")] {
+            let result = guess(hex_val, charset);
+            assert_eq!((result.data_type.as_str(), result.value.as_str()), ("NUMBER", number), "{}", hex_val);
+        }
+    }
+
+    #[test]
+    fn vector_norm() {
+        //FLOAT32 [1,2], with the norm stored under either flag
+        for flags in ["0000", "0002", "0010"] {
+            let norm = if flags == "0000" { "" } else { "3ff0000000000000" };
+            let image = hex::decode(format!("db00{}0200000002{}bf800000c0000000", flags, norm)).unwrap();
+            assert_eq!(check_vector(image).unwrap().value, "[1,2]", "flags {}", flags);
+        }
+    }
+
+    #[test]
+    fn timestamp_tz_region_name() {
+        let options = DecodeOptions { tz_regions: timezone::parse_regions("# generated\n340\tEurope/Warsaw\n"), ..Default::default() };
+        let result = check_timestamp_tz(TSTZ_REGION.to_vec(), &options).unwrap();
+        assert_eq!(result.value, "2000-01-01T01:00:00.000000000+01:00[Europe/Warsaw]");
+    }
+
+    #[test]
+    fn timestamp_tz_unknown_region() {
+        let options = DecodeOptions { tz_regions: HashMap::new(), ..Default::default() };
+        let result = check_timestamp_tz(TSTZ_REGION.to_vec(), &options).unwrap();
+        assert_eq!(result.value, "2000-01-01T00:00:00.000000000Z[region:340]");
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::HashSet;
+use crate::oracle_decoder;
+use crate::oracle_decoder::{DecodeOptions, OracleType};
+
+const OSON_MAGIC: [u8; 3] = [0xff, 0x4a, 0x5a];
+const OSON_VERSION_MAX_FNAME_255: u8 = 1;
+const OSON_VERSION_MAX_FNAME_65535: u8 = 3;
+
+const OSON_FLAG_REL_OFFSET_MODE: u16 = 0x0001;
+const OSON_FLAG_NUM_FNAMES_UINT32: u16 = 0x0008;
+const OSON_FLAG_IS_SCALAR: u16 = 0x0010;
+const OSON_FLAG_SEC_FNAMES_SEG_UINT16: u16 = 0x0100;
+const OSON_FLAG_NUM_FNAMES_UINT16: u16 = 0x0400;
+const OSON_FLAG_FNAMES_SEG_UINT32: u16 = 0x0800;
+const OSON_FLAG_TREE_SEG_UINT32: u16 = 0x1000;
+
+const OSON_TYPE_NULL: u8 = 0x30;
+const OSON_TYPE_TRUE: u8 = 0x31;
+const OSON_TYPE_FALSE: u8 = 0x32;
+const OSON_TYPE_STRING_LENGTH_UINT8: u8 = 0x33;
+const OSON_TYPE_NUMBER_LENGTH_UINT8: u8 = 0x35;
+const OSON_TYPE_BINARY_DOUBLE: u8 = 0x36;
+const OSON_TYPE_STRING_LENGTH_UINT16: u8 = 0x37;
+const OSON_TYPE_STRING_LENGTH_UINT32: u8 = 0x38;
+const OSON_TYPE_TIMESTAMP: u8 = 0x39;
+const OSON_TYPE_BINARY_LENGTH_UINT16: u8 = 0x3a;
+const OSON_TYPE_BINARY_LENGTH_UINT32: u8 = 0x3b;
+const OSON_TYPE_DATE: u8 = 0x3c;
+const OSON_TYPE_INTERVAL_YM: u8 = 0x3d;
+const OSON_TYPE_INTERVAL_DS: u8 = 0x3e;
+const OSON_TYPE_EXTENDED: u8 = 0x7b;
+const OSON_TYPE_TIMESTAMP7: u8 = 0x7c;
+const OSON_TYPE_TIMESTAMP_TZ: u8 = 0x7d;
+const OSON_TYPE_ID: u8 = 0x7e;
+const OSON_TYPE_BINARY_FLOAT: u8 = 0x7f;
+const OSON_TYPE_VECTOR: u8 = 0x01;
+
+//images are guessed from arbitrary row data, nesting is capped to keep corrupt ones off the stack
+const OSON_MAX_DEPTH: usize = 256;
+
+/// Oracle binary JSON: header, field name dictionary and a tree segment of nodes.
+/// Scalars reuse the regular Oracle type decoders, the result is JSON text.
+struct OsonDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    field_names: Vec<String>,
+    field_id_length: usize,
+    tree_seg_pos: usize,
+    relative_offsets: bool,
+    options: &'a DecodeOptions,
+    depth: usize,
+    //containers already decoded, a child offset pointing back to one of them is a loop
+    visited: HashSet<usize>,
+}
+
+impl<'a> OsonDecoder<'a> {
+    fn raw(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.data.len() {
+            return Err("OSON image too short".to_string());
+        }
+        let bytes_val = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes_val)
+    }
+
+    fn ub1(&mut self) -> Result<u8, String> {
+        Ok(self.raw(1)?[0])
+    }
+
+    fn ub2(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.raw(2)?.try_into().unwrap()))
+    }
+
+    fn ub4(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.raw(4)?.try_into().unwrap()))
+    }
+
+    fn decode(&mut self) -> Result<String, String> {
+        if self.raw(3)? != OSON_MAGIC {
+            return Err("Not an OSON image".to_string());
+        }
+        let version = self.ub1()?;
+        if version != OSON_VERSION_MAX_FNAME_255 && version != OSON_VERSION_MAX_FNAME_65535 {
+            return Err(format!("Unsupported OSON version {}", version));
+        }
+        let flags = self.ub2()?;
+        self.relative_offsets = flags & OSON_FLAG_REL_OFFSET_MODE != 0;
+
+        if flags & OSON_FLAG_IS_SCALAR != 0 {
+            if flags & OSON_FLAG_TREE_SEG_UINT32 != 0 {
+                self.raw(4)?;
+            } else {
+                self.raw(2)?;
+            }
+            return self.decode_node();
+        }
+
+        let num_short_names = if flags & OSON_FLAG_NUM_FNAMES_UINT32 != 0 {
+            self.field_id_length = 4;
+            self.ub4()? as usize
+        } else if flags & OSON_FLAG_NUM_FNAMES_UINT16 != 0 {
+            self.field_id_length = 2;
+            self.ub2()? as usize
+        } else {
+            self.field_id_length = 1;
+            self.ub1()? as usize
+        };
+
+        let (short_names_seg_size, short_offsets_size) = if flags & OSON_FLAG_FNAMES_SEG_UINT32 != 0 {
+            (self.ub4()? as usize, 4)
+        } else {
+            (self.ub2()? as usize, 2)
+        };
+
+        let mut num_long_names = 0;
+        let mut long_names_seg_size = 0;
+        let mut long_offsets_size = 4;
+        if version == OSON_VERSION_MAX_FNAME_65535 {
+            let secondary_flags = self.ub2()?;
+            if secondary_flags & OSON_FLAG_SEC_FNAMES_SEG_UINT16 != 0 {
+                long_offsets_size = 2;
+            }
+            num_long_names = self.ub4()? as usize;
+            long_names_seg_size = self.ub4()? as usize;
+        }
+
+        if flags & OSON_FLAG_TREE_SEG_UINT32 != 0 {
+            self.ub4()?;
+        } else {
+            self.ub2()?;
+        }
+        let _num_tiny_nodes = self.ub2()?;
+
+        self.read_field_names(num_short_names, 1, short_offsets_size, short_names_seg_size, 1)?;
+        self.read_field_names(num_long_names, 2, long_offsets_size, long_names_seg_size, 2)?;
+
+        self.tree_seg_pos = self.pos;
+        self.decode_node()
+    }
+
+    //hash ids, then name offsets, then the names segment (names are length prefixed)
+    fn read_field_names(&mut self, count: usize, hash_size: usize, offsets_size: usize, seg_size: usize, len_size: usize) -> Result<(), String> {
+        if count == 0 {
+            return Ok(());
+        }
+        self.raw(count * hash_size)?;
+        let offsets_pos = self.pos;
+        self.raw(count * offsets_size)?;
+        let names = self.raw(seg_size)?;
+        let final_pos = self.pos;
+
+        self.pos = offsets_pos;
+        for _ in 0..count {
+            let offset = if offsets_size == 2 { self.ub2()? as usize } else { self.ub4()? as usize };
+            let name_len = if len_size == 1 {
+                *names.get(offset).ok_or("Wrong OSON field name offset".to_string())? as usize
+            } else {
+                u16::from_be_bytes(names.get(offset..offset + 2).ok_or("Wrong OSON field name offset".to_string())?.try_into().unwrap()) as usize
+            };
+            let name = names.get(offset + len_size..offset + len_size + name_len).ok_or("Wrong OSON field name".to_string())?;
+            self.field_names.push(String::from_utf8_lossy(name).to_string());
+        }
+        self.pos = final_pos;
+        Ok(())
+    }
+
+    fn scalar(&mut self, len: usize, decoder: fn(Vec<u8>) -> Result<OracleType, String>) -> Result<String, String> {
+        let bytes_val = self.raw(len)?.to_vec();
+        Ok(decoder(bytes_val)?.value)
+    }
+
+    fn number(&mut self, len: usize) -> Result<String, String> {
+        if len == 0 {
+            return Ok("0".to_string());
+        }
+        let value = self.scalar(len, oracle_decoder::check_number)?;
+        //JSON has no infinities
+        if value.ends_with("Infinity") { Ok(json_string(&value)) } else { Ok(value) }
+    }
+
+    fn string(&mut self, len: usize) -> Result<String, String> {
+        let bytes_val = self.raw(len)?;
+        Ok(json_string(&String::from_utf8_lossy(bytes_val)))
+    }
+
+    fn decode_node(&mut self) -> Result<String, String> {
+        let node_type = self.ub1()?;
+        if node_type & 0x80 != 0 {
+            return self.decode_container(node_type);
+        }
+
+        match node_type {
+            OSON_TYPE_NULL => return Ok("null".to_string()),
+            OSON_TYPE_TRUE => return Ok("true".to_string()),
+            OSON_TYPE_FALSE => return Ok("false".to_string()),
+            OSON_TYPE_DATE | OSON_TYPE_TIMESTAMP7 => return Ok(json_string(&self.scalar(7, oracle_decoder::check_date)?.replace(' ', "T"))),
+            OSON_TYPE_TIMESTAMP => return Ok(json_string(&self.scalar(11, oracle_decoder::check_timestamp)?.replace(' ', "T"))),
+            OSON_TYPE_TIMESTAMP_TZ => {
+                let bytes_val = self.raw(13)?.to_vec();
+                return Ok(json_string(&oracle_decoder::check_timestamp_tz(bytes_val, self.options)?.value));
+            }
+            OSON_TYPE_BINARY_FLOAT => return self.scalar(4, oracle_decoder::check_binary_float),
+            OSON_TYPE_BINARY_DOUBLE => return self.scalar(8, oracle_decoder::check_binary_double),
+            OSON_TYPE_INTERVAL_YM => return Ok(json_string(&self.scalar(5, oracle_decoder::check_interval_ym)?)),
+            OSON_TYPE_INTERVAL_DS => return Ok(json_string(&self.scalar(11, oracle_decoder::check_interval_ds)?)),
+            OSON_TYPE_STRING_LENGTH_UINT8 => {
+                let len = self.ub1()? as usize;
+                return self.string(len);
+            }
+            OSON_TYPE_STRING_LENGTH_UINT16 => {
+                let len = self.ub2()? as usize;
+                return self.string(len);
+            }
+            OSON_TYPE_STRING_LENGTH_UINT32 => {
+                let len = self.ub4()? as usize;
+                return self.string(len);
+            }
+            OSON_TYPE_NUMBER_LENGTH_UINT8 => {
+                let len = self.ub1()? as usize;
+                return self.number(len);
+            }
+            OSON_TYPE_ID => {
+                let len = self.ub1()? as usize;
+                return Ok(json_string(&hex::encode(self.raw(len)?)));
+            }
+            OSON_TYPE_BINARY_LENGTH_UINT16 => {
+                let len = self.ub2()? as usize;
+                return Ok(json_string(&hex::encode(self.raw(len)?)));
+            }
+            OSON_TYPE_BINARY_LENGTH_UINT32 => {
+                let len = self.ub4()? as usize;
+                return Ok(json_string(&hex::encode(self.raw(len)?)));
+            }
+            OSON_TYPE_EXTENDED => {
+                let extended_type = self.ub1()?;
+                if extended_type != OSON_TYPE_VECTOR {
+                    return Err(format!("Unsupported OSON extended type {}", extended_type));
+                }
+                let len = self.ub4()? as usize;
+                return self.scalar(len, oracle_decoder::check_vector);
+            }
+            _ => {}
+        }
+
+        match node_type & 0xf0 {
+            //number with the length in the node type
+            0x20 | 0x60 => self.number((node_type & 0x0f) as usize + 1),
+            //integer with the length in the node type
+            0x40 | 0x50 => self.number((node_type & 0x0f) as usize),
+            //short string with the length in the node type
+            _ if node_type & 0xe0 == 0 => self.string(node_type as usize),
+            _ => Err(format!("Unsupported OSON node type {:#x}", node_type)),
+        }
+    }
+
+    fn num_children(&mut self, node_type: u8) -> Result<(usize, bool), String> {
+        match node_type & 0x18 {
+            0x00 => Ok((self.ub1()? as usize, false)),
+            0x08 => Ok((self.ub2()? as usize, false)),
+            0x10 => Ok((self.ub4()? as usize, false)),
+            _ => Ok((0, true)),
+        }
+    }
+
+    fn offset(&mut self, node_type: u8) -> Result<usize, String> {
+        if node_type & 0x20 != 0 { Ok(self.ub4()? as usize) } else { Ok(self.ub2()? as usize) }
+    }
+
+    fn decode_container(&mut self, node_type: u8) -> Result<String, String> {
+        let is_object = node_type & 0x40 == 0;
+        let container_offset = self.pos - self.tree_seg_pos - 1;
+        if self.depth == OSON_MAX_DEPTH {
+            return Err("OSON image nested too deeply".to_string());
+        }
+        if !self.visited.insert(container_offset) {
+            return Err(format!("OSON node {:#x} referenced twice", container_offset));
+        }
+        let (mut num_children, is_shared) = self.num_children(node_type)?;
+
+        let mut field_ids_pos = 0;
+        let mut offsets_pos;
+        if is_shared {
+            //objects with the same field names share the field id array of another node
+            let shared = self.offset(node_type)?;
+            offsets_pos = self.pos;
+            self.pos = self.tree_seg_pos + shared;
+            let shared_type = self.ub1()?;
+            num_children = self.num_children(shared_type)?.0;
+            field_ids_pos = self.pos;
+        } else if is_object {
+            field_ids_pos = self.pos;
+            offsets_pos = self.pos + self.field_id_length * num_children;
+        } else {
+            offsets_pos = self.pos;
+        }
+
+        //every child takes at least one byte, a corrupt count can't ask for more
+        let mut children: Vec<String> = Vec::with_capacity(num_children.min(self.data.len().saturating_sub(self.pos)));
+        for _ in 0..num_children {
+            let mut name = String::new();
+            if is_object {
+                self.pos = field_ids_pos;
+                let field_id = match self.field_id_length {
+                    1 => self.ub1()? as usize,
+                    2 => self.ub2()? as usize,
+                    _ => self.ub4()? as usize,
+                };
+                field_ids_pos = self.pos;
+                let field_name = self.field_names.get(field_id.wrapping_sub(1)).ok_or(format!("Wrong OSON field id {}", field_id))?;
+                name = json_string(field_name);
+            }
+
+            self.pos = offsets_pos;
+            let mut child_offset = self.offset(node_type)?;
+            if self.relative_offsets {
+                child_offset += container_offset;
+            }
+            offsets_pos = self.pos;
+            self.pos = self.tree_seg_pos + child_offset;
+            self.depth += 1;
+            let child = self.decode_node();
+            self.depth -= 1;
+            let child = child?;
+
+            if is_object {
+                children.push(format!("{}:{}", name, child));
+            } else {
+                children.push(child);
+            }
+        }
+
+        if is_object {
+            Ok(format!("{{{}}}", children.join(",")))
+        } else {
+            Ok(format!("[{}]", children.join(",")))
+        }
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+pub fn is_oson(bytes_val: &[u8]) -> bool {
+    bytes_val.len() > 6 && bytes_val[0..3] == OSON_MAGIC
+}
+
+pub fn check_oson(bytes_val: Vec<u8>, options: &DecodeOptions) -> Result<OracleType, String> {
+    if !is_oson(&bytes_val) {
+        return Err("Not an OSON image".to_string());
+    }
+
+    let mut decoder = OsonDecoder {
+        data: &bytes_val,
+        pos: 0,
+        field_names: Vec::new(),
+        field_id_length: 1,
+        tree_seg_pos: 0,
+        relative_offsets: false,
+        options,
+        depth: 0,
+        visited: HashSet::new(),
+    };
+    let value = decoder.decode()?;
+    Ok(OracleType{data_type: "JSON".to_string(), value})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //version 1 image without field names around a tree segment
+    fn image(tree: &[u8]) -> Vec<u8> {
+        let mut bytes_val = vec![0xff, 0x4a, 0x5a, 1, 0, 0, 0, 0, 0];
+        bytes_val.extend((tree.len() as u16).to_be_bytes());
+        bytes_val.extend([0, 0]);
+        bytes_val.extend(tree);
+        bytes_val
+    }
+
+    #[test]
+    fn nested_arrays() {
+        let result = check_oson(image(&[0xc0, 1, 0, 4, 0xc0, 1, 0, 8, 0x31]), &DecodeOptions::default()).unwrap();
+        assert_eq!(result.value, "[[true]]");
+    }
+
+    #[test]
+    fn array_containing_itself() {
+        assert!(check_oson(image(&[0xc0, 1, 0, 0]), &DecodeOptions::default()).is_err());
+    }
+
+    #[test]
+    fn arrays_nested_too_deeply() {
+        let mut tree: Vec<u8> = Vec::new();
+        for i in 1..=300_u16 {
+            tree.extend([0xc0, 1]);
+            tree.extend((i * 4).to_be_bytes());
+        }
+        tree.extend([0xc0, 0]);
+        assert!(check_oson(image(&tree), &DecodeOptions::default()).is_err());
+    }
+
+    #[test]
+    fn corrupt_child_count() {
+        assert!(check_oson(image(&[0xd0, 0xff, 0xff, 0xff, 0xff, 0, 0]), &DecodeOptions::default()).is_err());
+    }
+}
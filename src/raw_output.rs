@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::fs::read_to_string;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use crate::oracle_decoder;
+use crate::oracle_decoder::DecodeOptions;
+use crate::object_decoder;
+
+/// How original column bytes are kept next to the decoded values.
+/// Raw rows go to <objd>.raw as: objd|rdba|slot|col1|col2|... where a column is
+/// 0x<hex>, b64:<base64> or bin:<offset>:<length> (into <objd>.bin) and an empty column is NULL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RawOutput {
+    None,
+    Hex,
+    Base64,
+    Sidecar,
+}
+
+impl RawOutput {
+    pub fn from_name(name: &str) -> Result<RawOutput, String> {
+        match name.to_lowercase().as_str() {
+            "none" => Ok(RawOutput::None),
+            "hex" => Ok(RawOutput::Hex),
+            "base64" => Ok(RawOutput::Base64),
+            "sidecar" => Ok(RawOutput::Sidecar),
+            _ => Err(format!("Unknown raw output mode {}", name)),
+        }
+    }
+}
+
+//returns the offset at which the bytes were appended
+fn append_to_sidecar(fname: String, bytes_val: &[u8]) -> u64 {
+    let mut f_obj = File::options().append(true).create(true).open(fname).unwrap();
+    f_obj.write_all(bytes_val).unwrap();
+    f_obj.stream_position().unwrap() - bytes_val.len() as u64
+}
+
+pub fn raw_token(bytes_val: &[u8], mode: RawOutput, workdir: &str, objd: u32) -> String {
+    match mode {
+        RawOutput::None => String::new(),
+        RawOutput::Hex => format!("0x{}", hex::encode(bytes_val)),
+        RawOutput::Base64 => format!("b64:{}", STANDARD.encode(bytes_val)),
+        RawOutput::Sidecar => {
+            let offset = append_to_sidecar(format!("{}/{}.bin", workdir, objd), bytes_val);
+            format!("bin:{}:{}", offset, bytes_val.len())
+        }
+    }
+}
+
+pub fn parse_raw_token(token: &str, workdir: &str, objd: u32) -> Result<Vec<u8>, String> {
+    if let Some(h) = token.strip_prefix("0x") {
+        hex::decode(h).map_err(|e| e.to_string())
+    } else if let Some(b) = token.strip_prefix("b64:") {
+        STANDARD.decode(b).map_err(|e| e.to_string())
+    } else if let Some(location) = token.strip_prefix("bin:") {
+        let (offset, len) = location.split_once(':').ok_or(format!("Wrong raw token {}", token))?;
+        let offset: u64 = offset.parse().map_err(|_| format!("Wrong raw token {}", token))?;
+        let len: usize = len.parse().map_err(|_| format!("Wrong raw token {}", token))?;
+
+        let mut f = File::open(format!("{}/{}.bin", workdir, objd)).map_err(|e| e.to_string())?;
+        f.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+        let mut bytes_val = vec![0_u8; len];
+        f.read_exact(&mut bytes_val).map_err(|e| e.to_string())?;
+        Ok(bytes_val)
+    } else {
+        Err(format!("Wrong raw token {}", token))
+    }
+}
+
+/// Decodes a raw file of an earlier extraction again, with the current dictionary and options.
+/// Results go to <objd>.redecoded.csv, nothing is read from the source datafiles.
+pub fn redecode_file(fname: String, workdir: String, options: DecodeOptions) {
+    println!("Processing file {} ", fname);
+    let file_path = format!("{}/{}", workdir, fname);
+
+    for line in read_to_string(file_path).unwrap().lines() {
+        let fields: Vec<&str> = line.split('|').collect();
+        if fields.len() < 3 {
+            continue;
+        }
+        let objd: u32 = fields[0].parse().unwrap();
+        let columns = options.dictionary.table(objd).map(|t| t.columns.clone()).unwrap_or_default();
+
+        let mut row_columns: Vec<Option<Vec<u8>>> = Vec::new();
+        let mut row_string = String::new();
+        for token in &fields[3..] {
+            if token.is_empty() {
+                row_columns.push(None);
+                continue;
+            }
+            match parse_raw_token(token, &workdir, objd) {
+                Ok(bytes_val) => row_columns.push(Some(bytes_val)),
+                Err(e) => {
+                    println!("Row {}.{}: {}", fields[1], fields[2], e);
+                    row_columns.push(None);
+                }
+            }
+        }
+
+        let mut object_end = 0;
+        for (i, token) in fields[3..].iter().enumerate() {
+            if i < object_end {
+                continue;
+            }
+            let object_type = columns.get(i).and_then(|c| options.dictionary.type_def(&c.data_type)).filter(|t| t.is_object());
+            let value = if let Some(object_type) = object_type {
+                let (value, width) = object_decoder::decode_object(&row_columns[i..], object_type, &options);
+                object_end = i + width;
+                value
+            } else {
+                match &row_columns[i] {
+                    Some(bytes_val) => {
+                        let decoded = oracle_decoder::decode_column(bytes_val.clone(), &columns, i, &options);
+                        if decoded.data_type == "Unrecognized" { token.to_string() } else { decoded.value }
+                    }
+                    None if token.is_empty() => "NULL".to_string(),
+                    None => token.to_string(),
+                }
+            };
+            row_string = format!("{}|{}", row_string, value);
+        }
+
+        let mut f_obj = File::options().append(true).create(true).open(format!("{}/{}.redecoded.csv", workdir, objd)).unwrap();
+        f_obj.write_all(format!("{}\n", row_string).as_bytes()).unwrap();
+    }
+}
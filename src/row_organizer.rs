@@ -0,0 +1,191 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use crate::block_organizer;
+use crate::block_organizer::ExtractOptions;
+use crate::oracle_decoder::DecodeOptions;
+
+//row flag byte: K C H D F L P N
+pub const ROW_FLAG_CLUSTER: u8 = 0xc0;
+pub const ROW_FLAG_HEAD: u8 = 0x20;
+pub const ROW_FLAG_DELETED: u8 = 0x10;
+pub const ROW_FLAG_FIRST: u8 = 0x08;
+pub const ROW_FLAG_LAST: u8 = 0x04;
+pub const ROW_FLAG_PREV: u8 = 0x02;
+pub const ROW_FLAG_NEXT: u8 = 0x01;
+
+/// Column values of a row, NULL as None
+pub type RowColumns = Vec<Option<Vec<u8>>>;
+
+/// A piece of a chained or migrated row. Pieces without the L flag point to the next piece with nrid (dba, slot).
+#[derive(Debug, Clone)]
+pub struct RowPiece {
+    pub rdba: u32,
+    pub slot: u16,
+    pub flag: u8,
+    pub nrid: Option<(u32, u16)>,
+    pub columns: RowColumns,
+}
+
+/// Anything but a complete single-piece row (H-FL) of a heap table
+pub fn is_row_piece(flag: u8) -> bool {
+    let complete = ROW_FLAG_HEAD | ROW_FLAG_FIRST | ROW_FLAG_LAST;
+    flag & (ROW_FLAG_CLUSTER | ROW_FLAG_DELETED) == 0 && flag & (complete | ROW_FLAG_PREV | ROW_FLAG_NEXT) != 0 && flag != complete
+}
+
+fn pieces_file(workdir: &str, objd: u32) -> String {
+    format!("{}/{}.pieces", workdir, objd)
+}
+
+//rdba|slot|flag|nrid dba|nrid slot|col1|col2... - columns in hex, NULL as an empty field
+fn piece_to_line(piece: &RowPiece) -> String {
+    let (nrid_dba, nrid_slot) = match piece.nrid {
+        Some((dba, slot)) => (dba.to_string(), slot.to_string()),
+        None => (String::new(), String::new()),
+    };
+    let mut line = format!("{}|{}|{}|{}|{}", piece.rdba, piece.slot, piece.flag, nrid_dba, nrid_slot);
+    for c in &piece.columns {
+        line = format!("{}|{}", line, c.as_ref().map(hex::encode).unwrap_or_default());
+    }
+    line
+}
+
+fn piece_from_line(line: &str) -> Option<RowPiece> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 5 {
+        return None;
+    }
+    let nrid = match (fields[3].parse::<u32>(), fields[4].parse::<u16>()) {
+        (Ok(dba), Ok(slot)) => Some((dba, slot)),
+        _ => None,
+    };
+    let columns = fields[5..].iter().map(|c| if c.is_empty() { None } else { hex::decode(c).ok() }).collect();
+    Some(RowPiece { rdba: fields[0].parse().ok()?, slot: fields[1].parse().ok()?, flag: fields[2].parse().ok()?, nrid, columns })
+}
+
+/// Row pieces are kept in <objd>.pieces until all blocks were read
+pub fn save_piece(workdir: &str, objd: u32, piece: &RowPiece) {
+    let mut f_obj = File::options().append(true).create(true).open(pieces_file(workdir, objd)).unwrap();
+    f_obj.write_all(format!("{}\n", piece_to_line(piece)).as_bytes()).unwrap();
+}
+
+/// Some(true) for LONG RAW, Some(false) for LONG, None for other columns
+pub fn long_kind(column_type: Option<&str>, joined: bool, bytes_val: &[u8], options: &DecodeOptions) -> Option<bool> {
+    match column_type {
+        Some("LONG") => Some(false),
+        Some("LONG RAW") => Some(true),
+        Some(_) => None,
+        None if joined => Some(options.charset.decode(bytes_val).is_none()),
+        None => None,
+    }
+}
+
+/// LONG values up to long_inline_size go to the CSV, bigger ones to longs/<objd>_<rdba>_<slot>_<column>.txt|bin
+pub fn long_value(bytes_val: &[u8], long_raw: bool, location: (u32, u32, u16, usize), workdir: &str, options: &ExtractOptions) -> String {
+    let text = if long_raw { None } else { options.decode.charset.decode(bytes_val) };
+    if bytes_val.len() <= options.long_inline_size {
+        return text.unwrap_or_else(|| hex::encode(bytes_val));
+    }
+
+    let (objd, rdba, slot, col) = location;
+    let extension = if text.is_some() { "txt" } else { "bin" };
+    let fname = format!("longs/{}_{}_{}_{}.{}", objd, rdba, slot, col, extension);
+    fs::create_dir_all(format!("{}/longs", workdir)).unwrap();
+    let mut f_long = File::create(format!("{}/{}", workdir, fname)).unwrap();
+    match text {
+        Some(text) => f_long.write_all(text.as_bytes()).unwrap(),
+        None => f_long.write_all(bytes_val).unwrap(),
+    }
+    fname
+}
+
+//follows nrid from a head piece, None when a piece is missing
+fn chain_row(head: (u32, u16), pieces: &HashMap<(u32, u16), RowPiece>, visited: &mut HashSet<(u32, u16)>) -> Option<(RowColumns, Vec<usize>)> {
+    let mut row_columns: RowColumns = Vec::new();
+    let mut long_columns: Vec<usize> = Vec::new();
+    let mut chain: Vec<(u32, u16)> = Vec::new();
+    let mut current = head;
+
+    loop {
+        if chain.contains(&current) {
+            return None;
+        }
+        let piece = pieces.get(&current)?;
+        chain.push(current);
+
+        let mut piece_columns = piece.columns.clone().into_iter();
+        //the last column of the previous piece continues in this one (LONG split between blocks)
+        if piece.flag & ROW_FLAG_PREV != 0 && !row_columns.is_empty() {
+            if let Some(continued) = piece_columns.next() {
+                let last = row_columns.len() - 1;
+                let mut joined = row_columns[last].take().unwrap_or_default();
+                joined.extend(continued.unwrap_or_default());
+                row_columns[last] = Some(joined);
+                if !long_columns.contains(&last) {
+                    long_columns.push(last);
+                }
+            }
+        }
+        row_columns.extend(piece_columns);
+
+        match piece.nrid {
+            Some(nrid) if piece.flag & ROW_FLAG_LAST == 0 => current = nrid,
+            _ => break,
+        }
+    }
+
+    visited.extend(chain);
+    Some((row_columns, long_columns))
+}
+
+/// Joins row pieces saved during extraction into rows. Pieces of incomplete rows stay in <objd>.pieces,
+/// so that blocks from the next data file can complete them. Rows whose head piece wasn't found are
+/// assembled from their first piece only with headless, after the last data file.
+pub fn assemble_rows(workdir: &str, options: &ExtractOptions, headless: bool) {
+    let mut pieces_files: Vec<(u32, String)> = Vec::new();
+    for entry in fs::read_dir(workdir).unwrap().flatten() {
+        let fname = entry.file_name().to_string_lossy().to_string();
+        if let Some(objd) = fname.strip_suffix(".pieces").and_then(|o| o.parse::<u32>().ok()) {
+            pieces_files.push((objd, fname));
+        }
+    }
+
+    for (objd, fname) in pieces_files {
+        let content = fs::read_to_string(format!("{}/{}", workdir, fname)).unwrap();
+        let mut pieces: HashMap<(u32, u16), RowPiece> = HashMap::new();
+        let mut order: Vec<(u32, u16)> = Vec::new();
+        for piece in content.lines().filter_map(piece_from_line) {
+            let key = (piece.rdba, piece.slot);
+            if pieces.insert(key, piece).is_none() {
+                order.push(key);
+            }
+        }
+
+        //rows start at the head piece, rows whose head wasn't found start at the first piece
+        let start_flags: &[u8] = if headless { &[ROW_FLAG_HEAD, ROW_FLAG_FIRST] } else { &[ROW_FLAG_HEAD] };
+        let mut visited: HashSet<(u32, u16)> = HashSet::new();
+        let mut rows = 0;
+        for &start_flag in start_flags {
+            for key in &order {
+                let piece = &pieces[key];
+                if visited.contains(key) || piece.flag & start_flag == 0 || (start_flag == ROW_FLAG_FIRST && piece.flag & ROW_FLAG_PREV != 0) {
+                    continue;
+                }
+                if let Some((row_columns, long_columns)) = chain_row(*key, &pieces, &mut visited) {
+                    block_organizer::write_row(row_columns, &long_columns, objd, piece.rdba, piece.slot, workdir, options);
+                    rows += 1;
+                }
+            }
+        }
+
+        let left: Vec<String> = order.iter().filter(|k| !visited.contains(k)).map(|k| piece_to_line(&pieces[k])).collect();
+        println!("Object {}: {} rows assembled from row pieces, {} pieces left in {}", objd, rows, left.len(), fname);
+        if left.is_empty() {
+            fs::remove_file(format!("{}/{}", workdir, fname)).unwrap();
+        } else {
+            fs::write(format!("{}/{}", workdir, fname), format!("{}\n", left.join("\n"))).unwrap();
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use chrono::Utc;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use serde::{Deserialize, Serialize};
+use crate::memory_source::{MemoryRegion, MemorySource, RegionKind};
+use crate::memory_source;
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"RICOSNAP";
+const SNAPSHOT_VERSION: u32 = 1;
+//maps are stored in chunks, all-zero chunks are kept without data
+const SNAPSHOT_CHUNK: usize = 65536;
+//addr(8) len(4) compressed len(4)
+const RECORD_HEADER: u64 = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SnapshotMap {
+    start: u64,
+    size: u64,
+    perms: String,
+    name: String,
+}
+
+/// Metadata of a snapshot, stored as JSON after the magic and the format version
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SnapshotHeader {
+    pid: Option<u32>,
+    source: String,
+    timestamp: String,
+    maps: Vec<SnapshotMap>,
+}
+
+#[derive(Debug, Clone)]
+struct SnapshotChunk {
+    addr: u64,
+    len: u64,
+    offset: u64,
+    compressed_len: u32,
+}
+
+/// Snapshot file: RICOSNAP, version(4), header length(4), JSON header, then records of
+/// addr(8) len(4) compressed len(4) and deflated data. A compressed length of 0 is a chunk of zeros.
+/// Ranges that couldn't be read have no records.
+pub struct SnapshotFile {
+    pub path: String,
+    f: File,
+    header: SnapshotHeader,
+    chunks: Vec<SnapshotChunk>,
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+fn write_record(out: &mut BufWriter<File>, addr: u64, data: &[u8]) -> std::io::Result<u64> {
+    let compressed = if data.iter().all(|b| *b == 0) {
+        Vec::new()
+    } else {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(data)?;
+        encoder.finish()?
+    };
+    out.write_all(&addr.to_le_bytes())?;
+    out.write_all(&(data.len() as u32).to_le_bytes())?;
+    out.write_all(&(compressed.len() as u32).to_le_bytes())?;
+    out.write_all(&compressed)?;
+    Ok(RECORD_HEADER + compressed.len() as u64)
+}
+
+/// Writes the regions of a source to a snapshot file. Pages that can't be read are skipped with skip_granularity.
+pub fn write_snapshot(source: &mut dyn MemorySource, regions: &[MemoryRegion], spec: &str, path: &str, skip_granularity: u64) -> Result<(), String> {
+    let header = SnapshotHeader {
+        pid: memory_source::spec_pid(spec),
+        source: spec.to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        maps: regions.iter().map(|r| SnapshotMap { start: r.start, size: r.size, perms: r.perms.clone(), name: r.name.clone() }).collect(),
+    };
+    let header_json = serde_json::to_vec(&header).map_err(|e| e.to_string())?;
+
+    let mut out = BufWriter::new(File::create(path).map_err(|e| format!("Can't create snapshot {}: {}", path, e))?);
+    out.write_all(SNAPSHOT_MAGIC).map_err(|e| e.to_string())?;
+    out.write_all(&SNAPSHOT_VERSION.to_le_bytes()).map_err(|e| e.to_string())?;
+    out.write_all(&(header_json.len() as u32).to_le_bytes()).map_err(|e| e.to_string())?;
+    out.write_all(&header_json).map_err(|e| e.to_string())?;
+
+    let skip_granularity = skip_granularity.max(1);
+    let mut buffer = vec![0_u8; SNAPSHOT_CHUNK];
+    let (mut bytes_read, mut bytes_skipped, mut bytes_written) = (0_u64, 0_u64, 0_u64);
+    for region in regions {
+        println!("Snapshot of {} from {:#x} to {:#x}", region.name, region.start, region.start + region.size);
+        let end = region.start + region.size;
+        let mut pos = region.start;
+        while pos < end {
+            let want = SNAPSHOT_CHUNK.min((end - pos) as usize);
+            match source.read_at(pos, &mut buffer[..want]) {
+                Ok(n) if n > 0 => {
+                    bytes_written += write_record(&mut out, pos, &buffer[..n]).map_err(|e| e.to_string())?;
+                    bytes_read += n as u64;
+                    pos += n as u64;
+                }
+                _ => {
+                    let skip_to = ((pos / skip_granularity + 1) * skip_granularity).min(end);
+                    bytes_skipped += skip_to - pos;
+                    pos = skip_to;
+                }
+            }
+        }
+    }
+    out.flush().map_err(|e| e.to_string())?;
+
+    println!("Snapshot {} written: {} bytes read, {} bytes unreadable, {} bytes of records", path, bytes_read, bytes_skipped, bytes_written);
+    Ok(())
+}
+
+impl SnapshotFile {
+    pub fn open(path: &str) -> Result<SnapshotFile, String> {
+        let f = File::open(path).map_err(|e| format!("Can't open snapshot {}: {}", path, e))?;
+        let mut reader = BufReader::new(f.try_clone().map_err(|e| e.to_string())?);
+
+        let mut magic = [0_u8; 16];
+        reader.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        if &magic[0..8] != SNAPSHOT_MAGIC {
+            return Err(format!("{} is not a rico3 snapshot", path));
+        }
+        let version = u32::from_le_bytes(magic[8..12].try_into().unwrap());
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("Snapshot {} has version {}, only version {} is supported", path, version, SNAPSHOT_VERSION));
+        }
+        let header_len = u32::from_le_bytes(magic[12..16].try_into().unwrap()) as usize;
+        let mut header_json = vec![0_u8; header_len];
+        reader.read_exact(&mut header_json).map_err(|e| e.to_string())?;
+        let header: SnapshotHeader = serde_json::from_slice(&header_json).map_err(|e| e.to_string())?;
+
+        //index of the records, the data is read when needed
+        let mut chunks: Vec<SnapshotChunk> = Vec::new();
+        let mut offset = 16 + header_len as u64;
+        let mut record = [0_u8; RECORD_HEADER as usize];
+        while reader.read_exact(&mut record).is_ok() {
+            let chunk = SnapshotChunk {
+                addr: u64::from_le_bytes(record[0..8].try_into().unwrap()),
+                len: u32::from_le_bytes(record[8..12].try_into().unwrap()) as u64,
+                offset: offset + RECORD_HEADER,
+                compressed_len: u32::from_le_bytes(record[12..16].try_into().unwrap()),
+            };
+            reader.seek_relative(chunk.compressed_len as i64).map_err(|e| e.to_string())?;
+            offset = chunk.offset + chunk.compressed_len as u64;
+            chunks.push(chunk);
+        }
+        chunks.sort_by_key(|c| c.addr);
+
+        println!("Snapshot {} of {} (pid {}) taken at {}: {} maps", path, header.source, header.pid.map(|p| p.to_string()).unwrap_or("-".to_string()), header.timestamp, header.maps.len());
+        Ok(SnapshotFile { path: path.to_string(), f, header, chunks, cached: None })
+    }
+
+    fn chunk_data(&mut self, i: usize) -> std::io::Result<&[u8]> {
+        if self.cached.as_ref().map(|c| c.0) != Some(i) {
+            let chunk = &self.chunks[i];
+            let mut data = vec![0_u8; chunk.len as usize];
+            if chunk.compressed_len > 0 {
+                let mut compressed = vec![0_u8; chunk.compressed_len as usize];
+                self.f.seek(SeekFrom::Start(chunk.offset))?;
+                self.f.read_exact(&mut compressed)?;
+                DeflateDecoder::new(&compressed[..]).read_exact(&mut data)?;
+            }
+            self.cached = Some((i, data));
+        }
+        Ok(&self.cached.as_ref().unwrap().1)
+    }
+}
+
+impl MemorySource for SnapshotFile {
+    fn regions(&self) -> Vec<MemoryRegion> {
+        self.header.maps.iter().map(|m| MemoryRegion {
+            start: m.start,
+            size: m.size,
+            perms: m.perms.clone(),
+            name: m.name.clone(),
+            kind: memory_source::region_kind(&m.name).unwrap_or(RegionKind::Other),
+            score: 0,
+        }).collect()
+    }
+
+    /// Reads across consecutive chunks, stops at the first range that wasn't captured
+    fn read_at(&mut self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = addr + done as u64;
+            let i = match self.chunks.partition_point(|c| c.addr <= pos).checked_sub(1) {
+                Some(i) if pos < self.chunks[i].addr + self.chunks[i].len => i,
+                _ => break,
+            };
+            let chunk_addr = self.chunks[i].addr;
+            let data = self.chunk_data(i)?;
+            let from = (pos - chunk_addr) as usize;
+            let len = (buf.len() - done).min(data.len() - from);
+            buf[done..done + len].copy_from_slice(&data[from..from + len]);
+            done += len;
+        }
+        if done == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Address not captured in the snapshot"));
+        }
+        Ok(done)
+    }
+
+    fn reader(&self) -> Option<Box<dyn MemorySource + Send>> {
+        let f = File::open(&self.path).ok()?;
+        Some(Box::new(SnapshotFile { path: self.path.clone(), f, header: self.header.clone(), chunks: self.chunks.clone(), cached: None }))
+    }
+
+    fn process_addresses(&self) -> bool {
+        memory_source::spec_process_addresses(&self.header.source)
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use chrono::{FixedOffset, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+const EMBEDDED_REGIONS: &str = include_str!("timezone_regions.txt");
+
+pub fn embedded_regions() -> HashMap<u16, String> {
+    parse_regions(EMBEDDED_REGIONS)
+}
+
+/// Region table generated with the query in timezone_regions.txt, for the regions missing from the embedded one
+pub fn read_regions(fname: &str) -> Result<HashMap<u16, String>, String> {
+    let text = std::fs::read_to_string(fname).map_err(|e| format!("Can't read time zone regions {}: {}", fname, e))?;
+    Ok(parse_regions(&text))
+}
+
+//<region id><TAB><zone name> lines, # starts a comment
+pub fn parse_regions(text: &str) -> HashMap<u16, String> {
+    let mut regions: HashMap<u16, String> = HashMap::new();
+    for line in text.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.split('\t');
+        let id = fields.next().and_then(|f| f.trim().parse::<u16>().ok());
+        let name = fields.next().map(|f| f.trim().to_string());
+        if let (Some(id), Some(name)) = (id, name) {
+            regions.insert(id, name);
+        }
+    }
+    regions
+}
+
+pub fn format_with_offset(utc: NaiveDateTime, offset_minutes: i32) -> Result<String, String> {
+    let offset = FixedOffset::east_opt(offset_minutes * 60).ok_or("Wrong time zone offset".to_string())?;
+    let local = offset.from_utc_datetime(&utc);
+    Ok(local.format("%Y-%m-%dT%H:%M:%S%.9f%:z").to_string())
+}
+
+/// Region names unknown to the IANA database are reported as UTC with the name attached
+pub fn format_with_region(utc: NaiveDateTime, region: &str) -> String {
+    match region.parse::<Tz>() {
+        Ok(tz) => {
+            let local = tz.from_utc_datetime(&utc);
+            format!("{}[{}]", local.format("%Y-%m-%dT%H:%M:%S%.9f%:z"), region)
+        }
+        Err(_) => format!("{}[{}]", Utc.from_utc_datetime(&utc).format("%Y-%m-%dT%H:%M:%S%.9fZ"), region),
+    }
+}
+
+fn parse_offset(zone: &str) -> Option<FixedOffset> {
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let mut parts = zone[1..].split(':');
+    let hours: i32 = parts.next()?.parse().ok()?;
+    let minutes: i32 = parts.next().unwrap_or("0").parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Renders a local time of a zone given as "+02:00" / "-05:30" or a region name (select dbtimezone from dual)
+pub fn format_local(local: NaiveDateTime, zone: &str) -> Result<String, String> {
+    let zone = zone.trim();
+    if zone.starts_with('+') || zone.starts_with('-') {
+        let offset = parse_offset(zone).ok_or(format!("Wrong time zone offset {}", zone))?;
+        let dt = offset.from_local_datetime(&local).single().ok_or("Wrong local time".to_string())?;
+        Ok(dt.format("%Y-%m-%dT%H:%M:%S%.9f%:z").to_string())
+    } else {
+        let tz = zone.parse::<Tz>().map_err(|_| format!("Unknown time zone {}", zone))?;
+        let dt = tz.from_local_datetime(&local).earliest().ok_or("Wrong local time".to_string())?;
+        Ok(format!("{}[{}]", dt.format("%Y-%m-%dT%H:%M:%S%.9f%:z"), zone))
+    }
+}
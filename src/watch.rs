@@ -0,0 +1,118 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::block_organizer;
+use crate::block_organizer::BLOCK_SIZE;
+use crate::memory_source;
+use crate::memory_source::{MemoryRegion, MemorySource};
+
+/// Block versions captured by watching memory: watch/<objd>.dat with the blocks and watch/<objd>.versions
+/// with rdba|scn|offset in .dat|pass|time of every version. A version is kept once per (rdba, SCN).
+struct VersionStore {
+    workdir: String,
+    versions: HashSet<(u32, u64)>,
+    blocks: HashSet<u32>,
+}
+
+/// What to watch: blocks of objds, sampled every interval, passes times or until stopped
+pub struct WatchOptions {
+    pub objds: Vec<u32>,
+    pub interval: Duration,
+    pub passes: Option<u64>,
+}
+
+/// SCN of the last change of a block: wrap(2) at 12, base(4) at 8
+pub fn block_scn(block_data: &[u8]) -> u64 {
+    let base = u32::from_ne_bytes(block_data[8..12].try_into().unwrap()) as u64;
+    let wrap = u16::from_ne_bytes(block_data[12..14].try_into().unwrap()) as u64;
+    (wrap << 32) | base
+}
+
+fn block_objd(block_data: &[u8]) -> Option<u32> {
+    if block_data[0] == 6 && memory_source::looks_like_block(block_data) {
+        Some(u32::from_ne_bytes(block_data[24..28].try_into().unwrap()))
+    } else {
+        None
+    }
+}
+
+impl VersionStore {
+    /// Versions already in the store are loaded, so a watch can be continued
+    fn open(workdir: &str, objds: &[u32]) -> VersionStore {
+        let workdir = format!("{}/watch", workdir);
+        fs::create_dir_all(&workdir).unwrap();
+        let mut store = VersionStore { workdir, versions: HashSet::new(), blocks: HashSet::new() };
+        for objd in objds {
+            let index = fs::read_to_string(format!("{}/{}.versions", store.workdir, objd)).unwrap_or_default();
+            for line in index.lines() {
+                let fields: Vec<&str> = line.split('|').collect();
+                if let (Some(Ok(rdba)), Some(Ok(scn))) = (fields.first().map(|f| f.parse::<u32>()), fields.get(1).map(|f| f.parse::<u64>())) {
+                    store.versions.insert((rdba, scn));
+                    store.blocks.insert(rdba);
+                }
+            }
+        }
+        store
+    }
+
+    /// Appends the block when its version is new
+    fn add(&mut self, objd: u32, block_data: &[u8], pass: u64, time: &str) -> bool {
+        let rdba = u32::from_ne_bytes(block_data[4..8].try_into().unwrap());
+        let scn = block_scn(block_data);
+        if !self.versions.insert((rdba, scn)) {
+            return false;
+        }
+        self.blocks.insert(rdba);
+
+        let dat_name = format!("{}/{}.dat", self.workdir, objd);
+        let mut f_dat = File::options().append(true).create(true).open(&dat_name).unwrap();
+        let offset = f_dat.metadata().unwrap().len();
+        f_dat.write_all(block_data).unwrap();
+        let mut f_versions = File::options().append(true).create(true).open(format!("{}/{}.versions", self.workdir, objd)).unwrap();
+        f_versions.write_all(format!("{}|{}|{}|{}|{}\n", rdba, scn, offset, pass, time).as_bytes()).unwrap();
+        true
+    }
+}
+
+/// Samples the regions and keeps every new version of the blocks of the watched objects
+pub fn watch_objects_from_memory(source: &mut dyn MemorySource, regions: Vec<MemoryRegion>, workdir: String, watch: &WatchOptions, readers: u8, skip_granularity: u64) {
+    let objds = &watch.objds;
+    let mut store = VersionStore::open(&workdir, objds);
+    let report = format!("Watching objects {:?} in {} memory regions every {:?}, {} versions of {} blocks already stored", objds, regions.len(), watch.interval, store.versions.len(), store.blocks.len());
+    println!("{}", report);
+    block_organizer::write_log(workdir.clone(), report);
+
+    let mut pass = 0;
+    while watch.passes.map(|p| pass < p).unwrap_or(true) {
+        if pass > 0 {
+            std::thread::sleep(watch.interval);
+        }
+        pass += 1;
+
+        let found: Mutex<Vec<(u32, Vec<u8>)>> = Mutex::new(Vec::new());
+        let stats = memory_source::read_regions_parallel(source, &regions, readers, skip_granularity, |_, chunk_bytes| {
+            for block_data in chunk_bytes.chunks_exact(BLOCK_SIZE as usize) {
+                if let Some(objd) = block_objd(block_data).filter(|o| objds.contains(o)) {
+                    found.lock().unwrap().push((objd, block_data.to_vec()));
+                }
+            }
+        });
+
+        let time = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
+        let found = found.into_inner().unwrap();
+        let mut new_versions = 0;
+        for (objd, block_data) in &found {
+            if store.add(*objd, block_data, pass, &time) {
+                new_versions += 1;
+            }
+        }
+
+        print!("\rPass {}: {} blocks seen, {} new versions - {} unique blocks, {} versions captured", pass, found.len(), new_versions, store.blocks.len(), store.versions.len());
+        std::io::stdout().flush().unwrap();
+        block_organizer::write_log(workdir.clone(), format!("Watch pass {}: {} blocks seen, {} new versions, {} unique blocks, {} versions, {} unreadable ranges skipped", pass, found.len(), new_versions, store.blocks.len(), store.versions.len(), stats.skipped.len()));
+    }
+    println!();
+}